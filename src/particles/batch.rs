@@ -0,0 +1,442 @@
+use crate::particles::lighting::Lighting;
+use crate::particles::material::{self, BlendMode, MaterialSettings};
+use crate::particles::mesh::ParticleMesh;
+use crate::particles::render::RenderContext;
+use crate::particles::utils::ParticleQuad;
+use macroquad::models::{Mesh, Vertex};
+use macroquad::prelude::*;
+use macroquad::window::get_internal_gl;
+
+/// Index and vertex budgets of one mesh. macroquad batches at most 10000
+/// vertices and 5000 indices per draw call and clamps larger geometry, so
+/// meshes are filled to just under that.
+pub(crate) const MAX_INDICES: usize = 4800;
+const MAX_VERTICES: usize = 9600;
+
+/// Meshes filled one after another, each up to `MAX_VERTICES` and
+/// `MAX_INDICES`.
+///
+/// Meshes are kept between frames and refilled, so a batch of steady size
+/// doesn't allocate.
+struct MeshChunks {
+    meshes: Vec<Mesh>,
+    /// Number of meshes holding this frame's geometry; the rest are spare.
+    used: usize,
+}
+
+impl MeshChunks {
+    fn new() -> Self {
+        Self {
+            meshes: vec![],
+            used: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        for mesh in &mut self.meshes[..self.used] {
+            mesh.vertices.clear();
+            mesh.indices.clear();
+        }
+        self.used = 0;
+    }
+
+    /// The mesh to append `vertices` more vertices and `indices` more
+    /// indices to.
+    fn with_room(&mut self, vertices: usize, indices: usize) -> &mut Mesh {
+        let full = self.used == 0 || {
+            let last = &self.meshes[self.used - 1];
+            last.vertices.len() + vertices > MAX_VERTICES
+                || last.indices.len() + indices > MAX_INDICES
+        };
+        if full {
+            if self.used == self.meshes.len() {
+                self.meshes.push(Mesh {
+                    vertices: Vec::with_capacity(MAX_INDICES),
+                    indices: Vec::with_capacity(MAX_INDICES),
+                    texture: None,
+                });
+            }
+            self.used += 1;
+        }
+        &mut self.meshes[self.used - 1]
+    }
+
+    fn meshes(&self) -> &[Mesh] {
+        &self.meshes[..self.used]
+    }
+
+    /// Blend every vertex towards `ctx`'s fog, if it has one, by its
+    /// distance to the camera.
+    fn apply_fog(&mut self, ctx: &RenderContext, blend_mode: BlendMode) {
+        let Some(fog) = &ctx.fog else {
+            return;
+        };
+        for mesh in &mut self.meshes[..self.used] {
+            for vertex in &mut mesh.vertices {
+                let [r, g, b, a] = vertex.color;
+                let distance = vertex.position.distance(ctx.camera_position);
+                vertex.color = fog
+                    .apply(Color::from_rgba(r, g, b, a), distance, blend_mode)
+                    .into();
+            }
+        }
+    }
+
+    fn draw(&self, mode: DrawMode, texture: Option<&Texture2D>, settings: MaterialSettings) {
+        gl_use_material(&material::material(settings, mode == DrawMode::Lines));
+        // SAFETY: only called from the render thread between frames, while no
+        // other reference to macroquad's context is alive.
+        let gl = unsafe { get_internal_gl() }.quad_gl;
+        gl.texture(texture);
+        gl.draw_mode(mode);
+        for mesh in self.meshes() {
+            gl.geometry(&mesh.vertices, &mesh.indices);
+        }
+        gl.texture(None);
+        gl.draw_mode(DrawMode::Triangles);
+        gl_use_default_material();
+    }
+}
+
+/// Line geometry for a whole frame, collected into a few large meshes so
+/// thousands of particles cost a handful of draw calls instead of three per
+/// particle.
+pub struct LineBatch {
+    chunks: MeshChunks,
+}
+
+impl LineBatch {
+    pub fn new() -> Self {
+        Self {
+            chunks: MeshChunks::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Color) {
+        self.gradient_line(start, end, color, color);
+    }
+
+    /// A line whose colour blends from `start_color` to `end_color`.
+    pub fn gradient_line(&mut self, start: Vec3, end: Vec3, start_color: Color, end_color: Color) {
+        let mesh = self.chunks.with_room(2, 2);
+        let first = mesh.vertices.len() as u16;
+        mesh.vertices
+            .push(Vertex::new2(start, Vec2::ZERO, start_color));
+        mesh.vertices.push(Vertex::new2(end, Vec2::ZERO, end_color));
+        mesh.indices.extend([first, first + 1]);
+    }
+
+    /// Three axis-aligned lines of length `size` crossing at `p`, so the
+    /// particle reads as a point with depth from any angle.
+    pub fn cross(&mut self, p: Vec3, size: f32, color: Color) {
+        let s = size * 0.5;
+        self.line(p - vec3(s, 0.0, 0.0), p + vec3(s, 0.0, 0.0), color);
+        self.line(p - vec3(0.0, s, 0.0), p + vec3(0.0, s, 0.0), color);
+        self.line(p - vec3(0.0, 0.0, s), p + vec3(0.0, 0.0, s), color);
+    }
+
+    pub fn apply_fog(&mut self, ctx: &RenderContext, blend_mode: BlendMode) {
+        self.chunks.apply_fog(ctx, blend_mode);
+    }
+
+    pub fn draw(&self, settings: MaterialSettings) {
+        self.chunks.draw(DrawMode::Lines, None, settings);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn meshes(&self) -> &[Mesh] {
+        self.chunks.meshes()
+    }
+}
+
+/// Textured quads for a whole frame, batched like `LineBatch`.
+pub struct QuadBatch {
+    chunks: MeshChunks,
+}
+
+impl QuadBatch {
+    pub fn new() -> Self {
+        Self {
+            chunks: MeshChunks::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Add `quad` showing the `uv` region of the texture, tinted by `color`.
+    /// Corners map to the region's bottom-left, bottom-right, top-right and
+    /// top-left.
+    pub fn quad(&mut self, quad: &ParticleQuad, uv: Rect, color: Color) {
+        self.gradient_quad(quad, uv, [color; 4]);
+    }
+
+    /// Like `quad`, with a colour per corner, in the same order.
+    pub fn gradient_quad(&mut self, quad: &ParticleQuad, uv: Rect, colors: [Color; 4]) {
+        let mesh = self.chunks.with_room(4, 6);
+        let first = mesh.vertices.len() as u16;
+        let corners = [
+            (quad.0, vec2(uv.x, uv.y + uv.h)),
+            (quad.1, vec2(uv.x + uv.w, uv.y + uv.h)),
+            (quad.2, vec2(uv.x + uv.w, uv.y)),
+            (quad.3, vec2(uv.x, uv.y)),
+        ];
+        for ((position, uv), color) in corners.into_iter().zip(colors) {
+            mesh.vertices.push(Vertex::new2(position, uv, color));
+        }
+        mesh.indices
+            .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    pub fn apply_fog(&mut self, ctx: &RenderContext, blend_mode: BlendMode) {
+        self.chunks.apply_fog(ctx, blend_mode);
+    }
+
+    /// Draw the quads with `texture`, or untextured if it's `None`.
+    pub fn draw(&self, texture: Option<&Texture2D>, settings: MaterialSettings) {
+        self.chunks.draw(DrawMode::Triangles, texture, settings);
+    }
+}
+
+/// Copies of particle meshes for a whole frame, batched like `LineBatch`.
+pub struct MeshBatch {
+    chunks: MeshChunks,
+}
+
+impl MeshBatch {
+    pub fn new() -> Self {
+        Self {
+            chunks: MeshChunks::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Add `mesh` scaled by `size`, turned by `rotation` and moved to
+    /// `position`, tinted by `color` and shaded per vertex by `lighting`.
+    /// Unlit, faces turned away from the sky are darkened a little so the
+    /// shape still reads.
+    pub fn mesh(
+        &mut self,
+        mesh: &ParticleMesh,
+        position: Vec3,
+        rotation: Quat,
+        size: f32,
+        color: Color,
+        lighting: Option<&Lighting>,
+    ) {
+        let target = self
+            .chunks
+            .with_room(mesh.positions.len(), mesh.indices.len());
+        let first = target.vertices.len() as u16;
+        for (&vertex, &normal) in mesh.positions.iter().zip(&mesh.normals) {
+            let vertex = position + rotation * (vertex * size);
+            let normal = rotation * normal;
+            let color = match lighting {
+                Some(lighting) => lighting.shade(color, vertex, normal),
+                None => {
+                    let shade = 0.75 + 0.25 * normal.y;
+                    Color::new(color.r * shade, color.g * shade, color.b * shade, color.a)
+                }
+            };
+            target
+                .vertices
+                .push(Vertex::new2(vertex, Vec2::ZERO, color));
+        }
+        target
+            .indices
+            .extend(mesh.indices.iter().map(|&index| first + index));
+    }
+
+    /// A horizontal disc of `radius` around `center`, `color` in the middle
+    /// and fading to transparent at the rim.
+    pub fn soft_disc(&mut self, center: Vec3, radius: f32, color: Color) {
+        const SEGMENTS: u16 = 12;
+        let target = self
+            .chunks
+            .with_room(SEGMENTS as usize + 1, SEGMENTS as usize * 3);
+        let first = target.vertices.len() as u16;
+        let rim = Color { a: 0.0, ..color };
+        target
+            .vertices
+            .push(Vertex::new2(center, Vec2::ZERO, color));
+        for segment in 0..SEGMENTS {
+            let angle = segment as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+            let offset = vec3(angle.cos(), 0.0, angle.sin()) * radius;
+            target
+                .vertices
+                .push(Vertex::new2(center + offset, Vec2::ZERO, rim));
+        }
+        for segment in 0..SEGMENTS {
+            let next = (segment + 1) % SEGMENTS;
+            target
+                .indices
+                .extend([first, first + 1 + segment, first + 1 + next]);
+        }
+    }
+
+    pub fn apply_fog(&mut self, ctx: &RenderContext, blend_mode: BlendMode) {
+        self.chunks.apply_fog(ctx, blend_mode);
+    }
+
+    pub fn draw(&self, settings: MaterialSettings) {
+        self.chunks.draw(DrawMode::Triangles, None, settings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::render::RenderContext;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn crosses_are_three_coloured_lines() {
+        let mut batch = LineBatch::new();
+        batch.cross(vec3(1.0, 2.0, 3.0), 2.0, RED);
+        let mesh = &batch.chunks.meshes()[0];
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(mesh.vertices[0].position, vec3(0.0, 2.0, 3.0));
+        assert_eq!(mesh.vertices[3].position, vec3(1.0, 3.0, 3.0));
+        let red: [u8; 4] = RED.into();
+        assert!(mesh.vertices.iter().all(|v| v.color == red));
+    }
+
+    #[test]
+    fn large_batches_split_below_the_draw_call_limit() {
+        let mut batch = LineBatch::new();
+        for i in 0..2000 {
+            batch.cross(vec3(i as f32, 0.0, 0.0), 0.1, WHITE);
+        }
+        let meshes = batch.chunks.meshes();
+        assert_eq!(meshes.len(), 3);
+        assert_eq!(
+            meshes.iter().map(|m| m.indices.len()).sum::<usize>(),
+            2000 * 6
+        );
+        for mesh in meshes {
+            assert!(mesh.indices.len() <= MAX_INDICES);
+            // indices are local to their mesh
+            let max = *mesh.indices.iter().max().unwrap() as usize;
+            assert_eq!(max, mesh.vertices.len() - 1);
+        }
+
+        batch.clear();
+        batch.cross(Vec3::ZERO, 0.1, WHITE);
+        assert_eq!(batch.chunks.meshes().len(), 1);
+        assert_eq!(batch.chunks.meshes()[0].vertices.len(), 6);
+    }
+
+    #[test]
+    fn billboards_face_the_camera() {
+        let camera = Camera3D {
+            position: vec3(0.0, 0.0, 10.0),
+            target: Vec3::ZERO,
+            up: vec3(0.0, 1.0, 0.0),
+            ..Default::default()
+        };
+        let ctx = RenderContext::from_camera(&camera);
+        let quad = ParticleQuad::billboard(vec3(1.0, 2.0, 3.0), 2.0, 0.0, &ctx);
+        assert!(quad.0.abs_diff_eq(vec3(0.0, 1.0, 3.0), 1e-6));
+        assert!(quad.2.abs_diff_eq(vec3(2.0, 3.0, 3.0), 1e-6));
+
+        // a quarter turn moves the bottom-left corner to the bottom-right
+        let turned = ParticleQuad::billboard(vec3(1.0, 2.0, 3.0), 2.0, FRAC_PI_2, &ctx);
+        assert!(turned.0.abs_diff_eq(quad.1, 1e-6));
+        // still facing the camera
+        let normal = (turned.1 - turned.0).cross(turned.3 - turned.0);
+        assert!(normal.normalize().abs_diff_eq(vec3(0.0, 0.0, 1.0), 1e-6));
+    }
+
+    #[test]
+    fn segments_taper_and_face_the_camera() {
+        let camera = Camera3D {
+            position: vec3(0.0, 0.0, 10.0),
+            target: Vec3::ZERO,
+            up: vec3(0.0, 1.0, 0.0),
+            ..Default::default()
+        };
+        let ctx = RenderContext::from_camera(&camera);
+        let quad = ParticleQuad::segment(Vec3::ZERO, vec3(0.0, -4.0, 0.0), 2.0, 0.0, &ctx);
+        assert!((quad.0.distance(quad.3) - 2.0).abs() < 1e-6);
+        assert!(quad.1.abs_diff_eq(vec3(0.0, -4.0, 0.0), 1e-6));
+        assert_eq!(quad.0.z, 0.0);
+        assert_eq!(quad.3.z, 0.0);
+    }
+
+    #[test]
+    fn meshes_are_placed_and_share_chunks() {
+        let cube = ParticleMesh::cube();
+        let mut batch = MeshBatch::new();
+        batch.mesh(
+            &cube,
+            vec3(10.0, 0.0, 0.0),
+            Quat::IDENTITY,
+            2.0,
+            WHITE,
+            None,
+        );
+        batch.mesh(&cube, Vec3::ZERO, Quat::IDENTITY, 1.0, WHITE, None);
+        let mesh = &batch.chunks.meshes()[0];
+        assert_eq!(mesh.vertices.len(), 48);
+        assert!(
+            mesh.vertices[..24]
+                .iter()
+                .all(|v| (v.position.x - 10.0).abs() == 1.0)
+        );
+        // the second cube's indices point at its own vertices
+        assert_eq!(*mesh.indices[36..].iter().min().unwrap(), 24);
+    }
+
+    #[test]
+    fn meshes_with_spare_vertices_split_below_the_vertex_limit() {
+        // one triangle over 4000 vertices, most of them unreferenced
+        let mut obj = "v 0 0 0\n".repeat(4000);
+        obj.push_str("f 1 2 3\n");
+        let sparse = ParticleMesh::from_obj(&obj).unwrap();
+        let mut batch = MeshBatch::new();
+        for _ in 0..3 {
+            batch.mesh(&sparse, Vec3::ZERO, Quat::IDENTITY, 1.0, WHITE, None);
+        }
+        let meshes = batch.chunks.meshes();
+        assert_eq!(meshes.len(), 2);
+        for mesh in meshes {
+            assert!(mesh.vertices.len() <= MAX_VERTICES);
+        }
+        assert_eq!(meshes[1].indices, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn quads_carry_uvs_and_tint() {
+        let mut batch = QuadBatch::new();
+        let quad = ParticleQuad(
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            vec3(1.0, 1.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+        );
+        batch.quad(&quad, Rect::new(0.5, 0.25, 0.5, 0.25), BLUE);
+        let mesh = &batch.chunks.meshes()[0];
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        let uvs: Vec<Vec2> = mesh.vertices.iter().map(|v| v.uv).collect();
+        assert_eq!(
+            uvs,
+            vec![
+                vec2(0.5, 0.5),
+                vec2(1.0, 0.5),
+                vec2(1.0, 0.25),
+                vec2(0.5, 0.25)
+            ]
+        );
+        let blue: [u8; 4] = BLUE.into();
+        assert!(mesh.vertices.iter().all(|v| v.color == blue));
+    }
+}
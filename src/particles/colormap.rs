@@ -0,0 +1,276 @@
+use crate::particles::{particle::Particle, storage::ParticleStorage};
+use macroquad::prelude::*;
+use std::collections::HashMap;
+
+/// Perceptual colormaps for mapping a scalar to a colour.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Plasma,
+    Turbo,
+    /// Blue through grey to red, for values with a meaningful middle such
+    /// as a signed velocity.
+    Diverging,
+}
+
+// evenly spaced stops, interpolated linearly in between
+const VIRIDIS: &[u32] = &[
+    0x440154, 0x472d7b, 0x3b528b, 0x2c728e, 0x21918c, 0x28ae80, 0x5ec962, 0xaddc30, 0xfde725,
+];
+const MAGMA: &[u32] = &[
+    0x000004, 0x1c1044, 0x4f127b, 0x812581, 0xb5367a, 0xe55064, 0xfb8761, 0xfec287, 0xfcfdbf,
+];
+const PLASMA: &[u32] = &[
+    0x0d0887, 0x4c02a1, 0x7e03a8, 0xa92395, 0xcc4778, 0xe56b5d, 0xf89441, 0xfdc328, 0xf0f921,
+];
+const TURBO: &[u32] = &[
+    0x30123b, 0x4662d7, 0x36aaf9, 0x1ae4b6, 0x72fe5e, 0xc8ef34, 0xfaba39, 0xf66b19, 0x7a0403,
+];
+const DIVERGING: &[u32] = &[
+    0x3b4cc0, 0x7b9ff9, 0xc0d4f5, 0xdddddd, 0xf2cbb7, 0xee8468, 0xb40426,
+];
+
+impl Colormap {
+    /// Colour at `t`, from 0 (low) to 1 (high); clamped outside that.
+    pub fn sample(self, t: f32) -> Color {
+        let stops = match self {
+            Colormap::Viridis => VIRIDIS,
+            Colormap::Magma => MAGMA,
+            Colormap::Plasma => PLASMA,
+            Colormap::Turbo => TURBO,
+            Colormap::Diverging => DIVERGING,
+        };
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let index = (position as usize).min(stops.len() - 2);
+        let (low, high) = (
+            Color::from_hex(stops[index]),
+            Color::from_hex(stops[index + 1]),
+        );
+        let f = position - index as f32;
+        Color::new(
+            low.r + (high.r - low.r) * f,
+            low.g + (high.g - low.g) * f,
+            low.b + (high.b - low.b) * f,
+            1.0,
+        )
+    }
+}
+
+/// A per-particle scalar to colour by.
+#[derive(Clone, Copy)]
+pub enum ColorAttribute {
+    Speed,
+    /// Seconds since spawn.
+    Age,
+    Height,
+    /// Of a particle of unit mass.
+    KineticEnergy,
+    /// Particles per unit volume in the 3 × 3 × 3 block of grid cells
+    /// `cell_size` wide around each particle.
+    Density {
+        cell_size: f32,
+    },
+    /// Anything else, computed from the particle; `name` labels the legend.
+    Custom {
+        name: &'static str,
+        value: fn(&Particle) -> f32,
+    },
+}
+
+impl ColorAttribute {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorAttribute::Speed => "Speed",
+            ColorAttribute::Age => "Age",
+            ColorAttribute::Height => "Height",
+            ColorAttribute::KineticEnergy => "Kinetic energy",
+            ColorAttribute::Density { .. } => "Density",
+            ColorAttribute::Custom { name, .. } => name,
+        }
+    }
+}
+
+/// Values the colormap spans.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorRange {
+    /// From the lowest to the highest value of the current frame.
+    Auto,
+    Manual {
+        min: f32,
+        max: f32,
+    },
+}
+
+/// Computes an attribute for every particle, reusing its buffers between
+/// frames.
+pub(crate) struct AttributeValues {
+    values: Vec<f32>,
+    cells: HashMap<IVec3, u32>,
+    /// Range used for the latest values, for the legend.
+    pub(crate) range: (f32, f32),
+}
+
+impl AttributeValues {
+    pub(crate) fn new() -> Self {
+        Self {
+            values: vec![],
+            cells: HashMap::new(),
+            range: (0.0, 1.0),
+        }
+    }
+
+    /// Fill in `attribute` for every particle and resolve `range` against
+    /// them. Returns the values, indexed like the storage.
+    pub(crate) fn compute(
+        &mut self,
+        attribute: ColorAttribute,
+        range: ColorRange,
+        particles: &ParticleStorage,
+    ) -> &[f32] {
+        self.values.clear();
+        match attribute {
+            ColorAttribute::Density { cell_size } => {
+                let cell = |p: Vec3| (p / cell_size).floor().as_ivec3();
+                self.cells.clear();
+                for &position in particles.positions() {
+                    *self.cells.entry(cell(position)).or_default() += 1;
+                }
+                let volume = (3.0 * cell_size).powi(3);
+                for &position in particles.positions() {
+                    let center = cell(position);
+                    let mut count = 0;
+                    for x in -1..=1 {
+                        for y in -1..=1 {
+                            for z in -1..=1 {
+                                count += self.cells.get(&(center + ivec3(x, y, z))).unwrap_or(&0);
+                            }
+                        }
+                    }
+                    self.values.push(count as f32 / volume);
+                }
+            }
+            ColorAttribute::Speed => {
+                let speeds = particles.velocities().iter().map(|v| v.length());
+                self.values.extend(speeds)
+            }
+            ColorAttribute::Age => self.values.extend_from_slice(particles.ages()),
+            ColorAttribute::Height => {
+                let heights = particles.positions().iter().map(|p| p.y);
+                self.values.extend(heights)
+            }
+            ColorAttribute::KineticEnergy => {
+                let energies = particles
+                    .velocities()
+                    .iter()
+                    .map(|v| 0.5 * v.length_squared());
+                self.values.extend(energies)
+            }
+            ColorAttribute::Custom { value, .. } => {
+                self.values.extend(particles.iter().map(|p| value(&p)))
+            }
+        }
+
+        self.range = match range {
+            ColorRange::Manual { min, max } => (min, max),
+            ColorRange::Auto if self.values.is_empty() => (0.0, 1.0),
+            ColorRange::Auto => self
+                .values
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| {
+                    (min.min(v), max.max(v))
+                }),
+        };
+        &self.values
+    }
+
+    /// Colour of the particle at `index` through `colormap`.
+    pub(crate) fn color(&self, colormap: Colormap, index: usize) -> Color {
+        colormap.sample(self.normalize(self.values[index]))
+    }
+
+    /// Where `value` lies in the range, from 0 to 1.
+    fn normalize(&self, value: f32) -> f32 {
+        let (min, max) = self.range;
+        if max > min {
+            (value - min) / (max - min)
+        } else {
+            0.5
+        }
+    }
+}
+
+/// Draw a vertical colour bar for `colormap` in screen space, with `label`
+/// above it and the range's ends beside it. Call after `set_default_camera`.
+pub fn draw_colorbar(colormap: Colormap, label: &str, range: (f32, f32), x: f32, y: f32) {
+    let (width, height, steps) = (20.0, 200.0, 32);
+    draw_text(label, x, y - 8.0, 20.0, WHITE);
+    let step = height / steps as f32;
+    for i in 0..steps {
+        // high values at the top
+        let t = 1.0 - (i as f32 + 0.5) / steps as f32;
+        draw_rectangle(
+            x,
+            y + i as f32 * step,
+            width,
+            step + 0.5,
+            colormap.sample(t),
+        );
+    }
+    draw_rectangle_lines(x, y, width, height, 1.0, WHITE);
+    draw_text(
+        &format!("{:.2}", range.1),
+        x + width + 6.0,
+        y + 12.0,
+        18.0,
+        WHITE,
+    );
+    draw_text(
+        &format!("{:.2}", range.0),
+        x + width + 6.0,
+        y + height,
+        18.0,
+        WHITE,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colormaps_span_their_stops() {
+        assert_eq!(Colormap::Viridis.sample(0.0), Color::from_hex(0x440154));
+        assert_eq!(Colormap::Viridis.sample(1.0), Color::from_hex(0xfde725));
+        assert_eq!(Colormap::Diverging.sample(0.5), Color::from_hex(0xdddddd));
+        assert_eq!(Colormap::Turbo.sample(2.0), Colormap::Turbo.sample(1.0));
+    }
+
+    #[test]
+    fn values_resolve_their_range() {
+        let mut particles = ParticleStorage::new();
+        particles.push(Particle::at(Vec3::ZERO, vec3(3.0, 4.0, 0.0)));
+        particles.push(Particle::at(vec3(0.1, 0.0, 0.0), vec3(1.0, 0.0, 0.0)));
+        particles.push(Particle::at(vec3(50.0, 0.0, 0.0), Vec3::ZERO));
+
+        let mut values = AttributeValues::new();
+        let speeds = values
+            .compute(ColorAttribute::Speed, ColorRange::Auto, &particles)
+            .to_vec();
+        assert_eq!(speeds, vec![5.0, 1.0, 0.0]);
+        assert_eq!(values.range, (0.0, 5.0));
+        assert_eq!(values.normalize(1.0), 0.2);
+
+        // the first two are neighbours, the third is alone
+        let densities = values
+            .compute(
+                ColorAttribute::Density { cell_size: 1.0 },
+                ColorRange::Manual { min: 0.0, max: 1.0 },
+                &particles,
+            )
+            .to_vec();
+        assert_eq!(densities[0], densities[1]);
+        assert_eq!(densities[0], densities[2] * 2.0);
+        assert_eq!(values.range, (0.0, 1.0));
+    }
+}
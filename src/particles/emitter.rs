@@ -1,9 +1,15 @@
 use crate::particles::{
     particle::Particle,
-    utils::{Direction, Spawn, random_direction},
+    path::MotionPath,
+    sampling,
+    storage::ParticleStorage,
+    utils::{Direction, Spawn},
 };
 use macroquad::prelude::*;
+use macroquad::rand::RandGenerator;
+use std::f32::consts::{PI, TAU};
 
+#[derive(Clone)]
 pub enum Emitter {
     Point {
         position: Vec3,
@@ -23,37 +29,66 @@ pub enum Emitter {
 }
 
 impl Emitter {
-    pub fn spawn(&self, count: usize) -> Vec<Particle> {
-        let mut particles = Vec::new();
+    /// Current world-space position of the emitter.
+    pub fn position(&self) -> Vec3 {
+        match self {
+            Emitter::Point { position, .. }
+            | Emitter::Sphere { position, .. }
+            | Emitter::Cube { position, .. } => *position,
+        }
+    }
+
+    /// Move the emitter to a new world-space position.
+    pub fn set_position(&mut self, new_position: Vec3) {
+        match self {
+            Emitter::Point { position, .. }
+            | Emitter::Sphere { position, .. }
+            | Emitter::Cube { position, .. } => *position = new_position,
+        }
+    }
 
+    /// Spawn `count` particles of `particle_size` straight into `particles`,
+    /// drawing all randomness from `rng`. `scratch` holds the spawn offsets
+    /// and keeps its capacity between calls, so steady-state spawning
+    /// doesn't allocate.
+    pub fn spawn_into(
+        &self,
+        count: usize,
+        particle_size: f32,
+        rng: &RandGenerator,
+        particles: &mut ParticleStorage,
+        scratch: &mut Vec<Vec3>,
+    ) {
         match self {
             Emitter::Point {
                 position,
                 direction,
                 spread,
             } => {
+                // `spread` is the length of a random offset to the unit
+                // direction, so launches stay within asin(spread) of it; from
+                // 1 up they can go anywhere
+                let half_angle = if *spread < 1.0 {
+                    spread.max(0.0).asin()
+                } else {
+                    PI
+                };
                 for _ in 0..count {
-                    let base_velocity = match direction {
-                        Direction::Fixed(vec) => vec.normalize(),
-                        Direction::Random => random_direction(),
+                    let dir = match direction {
+                        Direction::Fixed(vec) => sampling::uniform_in_cone(rng, *vec, half_angle),
+                        Direction::Random => sampling::uniform_on_sphere(rng),
                     };
-
-                    let random_offset = vec3(
-                        rand::gen_range(-1.0, 1.0),
-                        rand::gen_range(-1.0, 1.0),
-                        rand::gen_range(-1.0, 1.0),
-                    )
-                    .normalize()
-                        * *spread;
-
-                    let velocity = (base_velocity + random_offset).normalize() * 2.0;
+                    let velocity = dir * 2.0;
 
                     particles.push(Particle {
                         position: *position,
                         prev_position: *position,
                         velocity,
                         energy: 1.0,
-                        size: 0.1,
+                        size: particle_size,
+                        rotation: rng.gen_range(0.0, TAU),
+                        variation: rng.gen_range(0.0, 1.0),
+                        age: 0.0,
                     })
                 }
             }
@@ -63,27 +98,31 @@ impl Emitter {
                 spawn_type,
             } => {
                 let radius = *size;
-                for _ in 0..count {
-                    let spawn_pos = match spawn_type {
-                        crate::particles::utils::Spawn::Volume => {
-                            // random point inside sphere (uniform)
-                            let dir = random_direction();
-                            let r: f32 = rand::gen_range(0.0f32, 1.0f32).powf(1.0 / 3.0) * radius;
-                            *position + dir * r
-                        }
-                        crate::particles::utils::Spawn::Surface => {
-                            // random point on sphere surface
-                            let dir = random_direction();
-                            *position + dir * radius
-                        }
-                    };
+                scratch.clear();
+                match spawn_type {
+                    // random point inside sphere (uniform)
+                    Spawn::Volume => {
+                        scratch.extend((0..count).map(|_| sampling::uniform_in_ball(rng, radius)))
+                    }
+                    // one point per equal-area band so each batch covers the sphere
+                    Spawn::Surface => scratch.extend(
+                        (0..count).map(|i| sampling::stratified_on_sphere(rng, i, count) * radius),
+                    ),
+                    Spawn::Even => {
+                        let spacing = even_spacing(4.0 * PI * radius * radius, count);
+                        sampling::poisson_disk(scratch, count, spacing, 8, || {
+                            sampling::uniform_on_sphere(rng) * radius
+                        });
+                    }
+                }
+
+                for &offset in scratch.iter() {
+                    let spawn_pos = *position + offset;
 
                     // velocity: for surface emit outward from center, for volume use random
                     let velocity = match spawn_type {
-                        crate::particles::utils::Spawn::Surface => {
-                            (spawn_pos - *position).normalize() * 2.0
-                        }
-                        crate::particles::utils::Spawn::Volume => random_direction() * 2.0,
+                        Spawn::Surface | Spawn::Even => offset.normalize_or_zero() * 2.0,
+                        Spawn::Volume => sampling::uniform_on_sphere(rng) * 2.0,
                     };
 
                     particles.push(Particle {
@@ -91,7 +130,10 @@ impl Emitter {
                         prev_position: spawn_pos,
                         velocity,
                         energy: 1.0,
-                        size: 0.1,
+                        size: particle_size,
+                        rotation: rng.gen_range(0.0, TAU),
+                        variation: rng.gen_range(0.0, 1.0),
+                        age: 0.0,
                     });
                 }
             }
@@ -102,61 +144,39 @@ impl Emitter {
             } => {
                 // treat `size` as full edge length
                 let half = *size / 2.0;
-                for _ in 0..count {
-                    let spawn_pos = match spawn_type {
-                        crate::particles::utils::Spawn::Volume => {
-                            // random point inside cube
-                            let x = rand::gen_range(-half, half);
-                            let y = rand::gen_range(-half, half);
-                            let z = rand::gen_range(-half, half);
-                            *position + vec3(x, y, z)
-                        }
-                        crate::particles::utils::Spawn::Surface => {
-                            // choose one of 6 faces uniformly
-                            let face = rand::gen_range(0, 6);
-                            let (x, y, z) = match face {
-                                0 => (
-                                    half,
-                                    rand::gen_range(-half, half),
-                                    rand::gen_range(-half, half),
-                                ),
-                                1 => (
-                                    -half,
-                                    rand::gen_range(-half, half),
-                                    rand::gen_range(-half, half),
-                                ),
-                                2 => (
-                                    rand::gen_range(-half, half),
-                                    half,
-                                    rand::gen_range(-half, half),
-                                ),
-                                3 => (
-                                    rand::gen_range(-half, half),
-                                    -half,
-                                    rand::gen_range(-half, half),
-                                ),
-                                4 => (
-                                    rand::gen_range(-half, half),
-                                    rand::gen_range(-half, half),
-                                    half,
-                                ),
-                                _ => (
-                                    rand::gen_range(-half, half),
-                                    rand::gen_range(-half, half),
-                                    -half,
-                                ),
-                            };
-                            *position + vec3(x, y, z)
-                        }
-                    };
+                scratch.clear();
+                match spawn_type {
+                    // random point inside cube
+                    Spawn::Volume => scratch.extend((0..count).map(|_| {
+                        vec3(
+                            rng.gen_range(-half, half),
+                            rng.gen_range(-half, half),
+                            rng.gen_range(-half, half),
+                        )
+                    })),
+                    Spawn::Surface => {
+                        scratch.extend((0..count).map(|_| cube_surface_point(rng, half)))
+                    }
+                    Spawn::Even => {
+                        let spacing = even_spacing(6.0 * *size * *size, count);
+                        sampling::poisson_disk(scratch, count, spacing, 8, || {
+                            cube_surface_point(rng, half)
+                        });
+                    }
+                }
 
-                    // velocity: surface -> face normal, volume -> random
+                for &offset in scratch.iter() {
+                    let spawn_pos = *position + offset;
+
+                    // velocity: surface -> away from the centre, even -> cosine-weighted
+                    // around the face normal, volume -> random
                     let velocity = match spawn_type {
-                        crate::particles::utils::Spawn::Surface => {
-                            // approximate normal from position relative to center
-                            (spawn_pos - *position).normalize() * 2.0
+                        // approximate normal from position relative to center
+                        Spawn::Surface => offset.normalize_or_zero() * 2.0,
+                        Spawn::Even => {
+                            sampling::cosine_hemisphere(rng, cube_face_normal(offset)) * 2.0
                         }
-                        crate::particles::utils::Spawn::Volume => random_direction() * 2.0,
+                        Spawn::Volume => sampling::uniform_on_sphere(rng) * 2.0,
                     };
 
                     particles.push(Particle {
@@ -164,12 +184,135 @@ impl Emitter {
                         prev_position: spawn_pos,
                         velocity,
                         energy: 1.0,
-                        size: 0.1,
+                        size: particle_size,
+                        rotation: rng.gen_range(0.0, TAU),
+                        variation: rng.gen_range(0.0, 1.0),
+                        age: 0.0,
                     });
                 }
             }
         }
+    }
+}
+
+/// One of the emitters feeding a `ParticleSystem`, with its own spawn rate,
+/// transform, optional motion path and enable flag.
+#[derive(Clone)]
+pub struct NamedEmitter {
+    pub name: String,
+    pub emitter: Emitter,
+    pub spawn_per_update: usize,
+    pub rotation: Quat,
+    pub enabled: bool,
+    path: Option<MotionPath>,
+    /// Position at the end of the previous update, used to spread spawns
+    /// along the path the emitter travelled during the frame.
+    last_position: Option<Vec3>,
+}
+
+impl NamedEmitter {
+    pub fn new(name: impl Into<String>, emitter: Emitter) -> Self {
+        Self {
+            name: name.into(),
+            emitter,
+            spawn_per_update: 2,
+            rotation: Quat::IDENTITY,
+            enabled: true,
+            path: None,
+            last_position: None,
+        }
+    }
+
+    pub fn spawn_rate(mut self, per_update: usize) -> Self {
+        self.spawn_per_update = per_update;
+        self
+    }
+
+    /// Rotate the emitter's shape and launch directions.
+    pub fn rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Drive the emitter's position and rotation from a motion path.
+    pub fn path(mut self, path: MotionPath) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    pub fn path_mut(&mut self) -> Option<&mut MotionPath> {
+        self.path.as_mut()
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.emitter.position()
+    }
+
+    /// Move the emitter. Particles spawned on the next update are spread
+    /// along the segment between the old and the new position.
+    pub fn set_position(&mut self, position: Vec3) {
+        self.emitter.set_position(position);
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        // don't smear the first spawns after re-enabling across the gap
+        self.last_position = None;
+    }
+
+    /// Advance the motion path by `delta` and return the emitter position at
+    /// the start and at the end of the frame.
+    pub(crate) fn step(&mut self, delta: f32) -> (Vec3, Vec3) {
+        if let Some(path) = &mut self.path {
+            path.advance(delta);
+            let (position, rotation) = path.current();
+            self.emitter.set_position(position);
+            self.rotation = rotation;
+        }
+
+        let current = self.emitter.position();
+        let previous = self.last_position.unwrap_or(current);
+        self.last_position = Some(current);
+        (previous, current)
+    }
+
+    pub(crate) fn draw_path(&self) {
+        if let Some(path) = &self.path {
+            path.draw(Color::new(1.0, 0.8, 0.2, 0.8));
+        }
+    }
+}
 
-        return particles;
+/// Uniformly distributed point on the surface of a cube centred at the origin.
+fn cube_surface_point(rng: &RandGenerator, half: f32) -> Vec3 {
+    // choose one of 6 faces uniformly
+    let face = rng.gen_range(0, 6);
+    let u = rng.gen_range(-half, half);
+    let v = rng.gen_range(-half, half);
+    match face {
+        0 => vec3(half, u, v),
+        1 => vec3(-half, u, v),
+        2 => vec3(u, half, v),
+        3 => vec3(u, -half, v),
+        4 => vec3(u, v, half),
+        _ => vec3(u, v, -half),
     }
 }
+
+/// Outward normal of the cube face closest to `offset`.
+fn cube_face_normal(offset: Vec3) -> Vec3 {
+    let a = offset.abs();
+    if a.x >= a.y && a.x >= a.z {
+        vec3(offset.x.signum(), 0.0, 0.0)
+    } else if a.y >= a.z {
+        vec3(0.0, offset.y.signum(), 0.0)
+    } else {
+        vec3(0.0, 0.0, offset.z.signum())
+    }
+}
+
+/// Minimum distance for `count` Poisson-disk points on a surface of `area`,
+/// loose enough that dart throwing rarely has to give up.
+fn even_spacing(area: f32, count: usize) -> f32 {
+    (area / count.max(1) as f32).sqrt() * 0.75
+}
@@ -0,0 +1,249 @@
+use crate::particles::{
+    batch::LineBatch,
+    colormap::Colormap,
+    force::Force,
+    material::{BlendMode, MaterialSettings},
+    render::RenderContext,
+};
+use macroquad::prelude::*;
+use std::cell::RefCell;
+
+/// Where a `FieldOverlay` samples the forces.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FieldRegion {
+    /// `counts` samples along each axis of the box from `min` to `max`.
+    Grid { min: Vec3, max: Vec3, counts: UVec3 },
+    /// `count` × `count` samples over a square `size` wide, centred on
+    /// `center` and facing `normal`.
+    Slice {
+        center: Vec3,
+        normal: Vec3,
+        size: f32,
+        count: u32,
+    },
+}
+
+impl FieldRegion {
+    /// Replace `points` with the region's sample positions.
+    pub(crate) fn points(&self, points: &mut Vec<Vec3>) {
+        // evenly spaced over the whole span, or the middle for one sample
+        let spread = |count: u32, i: u32| {
+            if count > 1 {
+                i as f32 / (count - 1) as f32
+            } else {
+                0.5
+            }
+        };
+        points.clear();
+        match *self {
+            FieldRegion::Grid { min, max, counts } => {
+                for z in 0..counts.z {
+                    for y in 0..counts.y {
+                        for x in 0..counts.x {
+                            let t = vec3(
+                                spread(counts.x, x),
+                                spread(counts.y, y),
+                                spread(counts.z, z),
+                            );
+                            points.push(min + (max - min) * t);
+                        }
+                    }
+                }
+            }
+            FieldRegion::Slice {
+                center,
+                normal,
+                size,
+                count,
+            } => {
+                let u = normal.normalize_or_zero().any_orthonormal_vector();
+                let v = normal.normalize_or_zero().cross(u);
+                for j in 0..count {
+                    for i in 0..count {
+                        let (a, b) = (spread(count, i) - 0.5, spread(count, j) - 0.5);
+                        points.push(center + (u * a + v * b) * size);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Arrows showing the acceleration a system's forces give a particle at
+/// points over a region, coloured through `colormap` and scaled by its
+/// magnitude, plus streamlines traced through the field from `seeds`. For
+/// seeing what forces do while tuning them.
+pub struct FieldOverlay {
+    pub region: FieldRegion,
+    /// Length of the strongest arrow; weaker ones are shorter in proportion.
+    pub arrow_length: f32,
+    /// Magnitude drawn at full length and at the top of the colormap, or
+    /// `None` for the strongest sample of the frame.
+    pub max_magnitude: Option<f32>,
+    pub colormap: Colormap,
+    /// Velocity of the particle the field is sampled for, since drag only
+    /// acts on moving particles. Zero by default.
+    pub probe_velocity: Vec3,
+    /// Points streamlines start from; none by default.
+    pub seeds: Vec<Vec3>,
+    /// Length of each streamline segment.
+    pub step: f32,
+    /// Most segments a streamline is traced for.
+    pub max_steps: usize,
+    points: RefCell<Vec<Vec3>>,
+    batch: RefCell<LineBatch>,
+}
+
+impl FieldOverlay {
+    pub fn new(region: FieldRegion) -> Self {
+        Self {
+            region,
+            arrow_length: 1.0,
+            max_magnitude: None,
+            colormap: Colormap::Viridis,
+            probe_velocity: Vec3::ZERO,
+            seeds: vec![],
+            step: 0.25,
+            max_steps: 200,
+            points: RefCell::new(vec![]),
+            batch: RefCell::new(LineBatch::new()),
+        }
+    }
+
+    /// Trace streamlines from `seeds`.
+    pub fn streamlines(mut self, seeds: Vec<Vec3>) -> Self {
+        self.seeds = seeds;
+        self
+    }
+
+    /// Acceleration `forces` give the probe particle at `position`.
+    fn field(&self, forces: &[Force], position: Vec3) -> Vec3 {
+        forces
+            .iter()
+            .map(|force| force.acceleration(position, self.probe_velocity))
+            .sum()
+    }
+
+    /// Points along the field line through `seed`, traced with the midpoint
+    /// method. Stops where the field vanishes or turns back on itself, as it
+    /// does at the centre of an attractor.
+    pub(crate) fn streamline(&self, forces: &[Force], seed: Vec3) -> Vec<Vec3> {
+        let mut line = vec![seed];
+        let mut position = seed;
+        let mut previous = Vec3::ZERO;
+        for _ in 0..self.max_steps {
+            let direction = self.field(forces, position).normalize_or_zero();
+            let midpoint = position + direction * self.step * 0.5;
+            let direction = self.field(forces, midpoint).normalize_or_zero();
+            if direction == Vec3::ZERO || direction.dot(previous) < 0.0 {
+                break;
+            }
+            position += direction * self.step;
+            previous = direction;
+            line.push(position);
+        }
+        line
+    }
+
+    /// Draw the arrows and streamlines for `forces`, such as a system's
+    /// `active_forces`.
+    pub fn draw(&self, forces: &[Force], ctx: &RenderContext) {
+        let mut points = self.points.borrow_mut();
+        self.region.points(&mut points);
+        let max = self.max_magnitude.unwrap_or_else(|| {
+            points
+                .iter()
+                .map(|&p| self.field(forces, p).length())
+                .fold(0.0, f32::max)
+        });
+        let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+        let color = |magnitude: f32| self.colormap.sample(magnitude * scale);
+
+        let mut batch = self.batch.borrow_mut();
+        batch.clear();
+        for &start in points.iter() {
+            let acceleration = self.field(forces, start);
+            let magnitude = acceleration.length();
+            if magnitude == 0.0 {
+                continue;
+            }
+            let length = self.arrow_length * (magnitude * scale).min(1.0);
+            let direction = acceleration / magnitude;
+            let tip = start + direction * length;
+            // head in the plane facing the camera so it reads from any angle
+            let side = direction
+                .cross(ctx.camera_position - tip)
+                .normalize_or_zero();
+            let back = tip - direction * length * 0.3;
+            let c = color(magnitude);
+            batch.line(start, tip, c);
+            batch.line(tip, back + side * length * 0.15, c);
+            batch.line(tip, back - side * length * 0.15, c);
+        }
+
+        for &seed in &self.seeds {
+            let line = self.streamline(forces, seed);
+            for pair in line.windows(2) {
+                let start = color(self.field(forces, pair[0]).length());
+                let end = color(self.field(forces, pair[1]).length());
+                batch.gradient_line(pair[0], pair[1], start, end);
+            }
+        }
+
+        batch.apply_fog(ctx, BlendMode::Alpha);
+        batch.draw(MaterialSettings::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_cover_their_extent() {
+        let mut points = vec![];
+        FieldRegion::Grid {
+            min: Vec3::splat(-1.0),
+            max: Vec3::splat(1.0),
+            counts: uvec3(3, 2, 1),
+        }
+        .points(&mut points);
+        assert_eq!(points.len(), 6);
+        assert_eq!(points[0], vec3(-1.0, -1.0, 0.0));
+        assert_eq!(points[5], vec3(1.0, 1.0, 0.0));
+
+        FieldRegion::Slice {
+            center: vec3(0.0, 2.0, 0.0),
+            normal: Vec3::Y,
+            size: 4.0,
+            count: 5,
+        }
+        .points(&mut points);
+        assert_eq!(points.len(), 25);
+        assert!(points.iter().all(|p| p.y == 2.0));
+        assert_eq!(points[12], vec3(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn streamlines_follow_the_field_and_stop_at_sinks() {
+        let overlay = FieldOverlay::new(FieldRegion::Slice {
+            center: Vec3::ZERO,
+            normal: Vec3::Y,
+            size: 1.0,
+            count: 1,
+        });
+
+        let gravity = [Force::Gravity(vec3(0.0, -9.8, 0.0))];
+        let fall = overlay.streamline(&gravity, Vec3::ZERO);
+        assert_eq!(fall.len(), overlay.max_steps + 1);
+        assert!((fall[1] - vec3(0.0, -0.25, 0.0)).length() < 1e-6);
+
+        let attractor = [Force::Attractor {
+            position: Vec3::ZERO,
+            strength: 10.0,
+        }];
+        let line = overlay.streamline(&attractor, vec3(5.0, 0.0, 0.0));
+        assert!(line.len() < overlay.max_steps);
+        assert!(line.last().unwrap().length() <= overlay.step);
+    }
+}
@@ -0,0 +1,89 @@
+use macroquad::prelude::*;
+
+/// How a particle steps through a flipbook's frames.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Playback {
+    /// Play the sheet once over the particle's lifetime, ignoring `fps`.
+    OverLifetime,
+    /// Loop at `fps` from the first frame.
+    Loop,
+    /// Loop at `fps` from a random frame, so particles don't animate in step.
+    RandomStart,
+}
+
+/// A sprite sheet of `rows` × `columns` equally sized frames, read left to
+/// right and top to bottom.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Flipbook {
+    pub rows: u32,
+    pub columns: u32,
+    pub fps: f32,
+    pub playback: Playback,
+}
+
+impl Flipbook {
+    /// `rows` and `columns` must be non-zero.
+    pub fn new(rows: u32, columns: u32, fps: f32, playback: Playback) -> Self {
+        assert!(
+            rows > 0 && columns > 0,
+            "flipbook needs at least one row and column, got {rows} × {columns}"
+        );
+        Self {
+            rows,
+            columns,
+            fps,
+            playback,
+        }
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.rows * self.columns
+    }
+
+    /// Frame shown by a particle of `age` seconds with `energy` left and
+    /// the given spawn `variation`.
+    pub fn frame(&self, age: f32, energy: f32, variation: f32) -> u32 {
+        let frames = self.frame_count();
+        let played = (age * self.fps) as u32;
+        match self.playback {
+            Playback::OverLifetime => (((1.0 - energy) * frames as f32) as u32).min(frames - 1),
+            Playback::Loop => played % frames,
+            Playback::RandomStart => ((variation * frames as f32) as u32 + played) % frames,
+        }
+    }
+
+    /// Region of the texture holding `frame`, in UV coordinates.
+    pub fn uv(&self, frame: u32) -> Rect {
+        let (w, h) = (1.0 / self.columns as f32, 1.0 / self.rows as f32);
+        let (row, column) = (frame / self.columns, frame % self.columns);
+        Rect::new(column as f32 * w, row as f32 * h, w, h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_follow_the_playback_mode() {
+        let lifetime = Flipbook::new(2, 4, 10.0, Playback::OverLifetime);
+        assert_eq!(lifetime.frame(5.0, 1.0, 0.0), 0);
+        assert_eq!(lifetime.frame(5.0, 0.5, 0.0), 4);
+        assert_eq!(lifetime.frame(5.0, 0.0, 0.0), 7);
+
+        let looping = Flipbook::new(2, 4, 10.0, Playback::Loop);
+        assert_eq!(looping.frame(0.35, 1.0, 0.9), 3);
+        assert_eq!(looping.frame(0.85, 1.0, 0.9), 0);
+
+        let random = Flipbook::new(2, 4, 10.0, Playback::RandomStart);
+        assert_eq!(random.frame(0.0, 1.0, 0.5), 4);
+        assert_eq!(random.frame(0.45, 1.0, 0.5), 0);
+    }
+
+    #[test]
+    fn frames_map_to_their_cell() {
+        let flipbook = Flipbook::new(2, 4, 10.0, Playback::Loop);
+        assert_eq!(flipbook.uv(0), Rect::new(0.0, 0.0, 0.25, 0.5));
+        assert_eq!(flipbook.uv(6), Rect::new(0.5, 0.5, 0.25, 0.5));
+    }
+}
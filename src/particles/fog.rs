@@ -0,0 +1,91 @@
+use crate::particles::material::BlendMode;
+use macroquad::prelude::*;
+
+/// How fog thickens with distance from the camera.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FogFalloff {
+    /// None before `start`, ramping up to full at `end`.
+    Linear { start: f32, end: f32 },
+    /// `1 - e^(-density * distance)`: thin close up, never quite full.
+    Exponential { density: f32 },
+}
+
+/// Distance fog blending colours toward `color`, which should match the
+/// scene's background so distant things sink into it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Fog {
+    pub color: Color,
+    pub falloff: FogFalloff,
+}
+
+impl Fog {
+    pub fn linear(color: Color, start: f32, end: f32) -> Self {
+        Self {
+            color,
+            falloff: FogFalloff::Linear { start, end },
+        }
+    }
+
+    pub fn exponential(color: Color, density: f32) -> Self {
+        Self {
+            color,
+            falloff: FogFalloff::Exponential { density },
+        }
+    }
+
+    /// How much fog lies between the camera and something `distance` away,
+    /// from 0 (none) to 1 (hidden).
+    pub fn amount(&self, distance: f32) -> f32 {
+        match self.falloff {
+            FogFalloff::Linear { start, end } => {
+                ((distance - start) / (end - start).max(1e-6)).clamp(0.0, 1.0)
+            }
+            FogFalloff::Exponential { density } => 1.0 - (-density * distance).exp(),
+        }
+    }
+
+    /// `color` seen through the fog from `distance` away, when drawn with
+    /// `blend_mode`. Additive colour fades to nothing rather than to the fog
+    /// colour, which it would otherwise add on top of the background, and
+    /// multiplied colour fades to white, which leaves the background as is.
+    pub fn apply(&self, color: Color, distance: f32, blend_mode: BlendMode) -> Color {
+        let amount = self.amount(distance);
+        let target = match blend_mode {
+            BlendMode::Alpha | BlendMode::Premultiplied => self.color,
+            BlendMode::Additive => BLACK,
+            BlendMode::Multiply => WHITE,
+        };
+        Color::new(
+            color.r + (target.r - color.r) * amount,
+            color.g + (target.g - color.g) * amount,
+            color.b + (target.b - color.b) * amount,
+            color.a,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fog_thickens_with_distance() {
+        let linear = Fog::linear(BLACK, 10.0, 20.0);
+        assert_eq!(linear.amount(5.0), 0.0);
+        assert_eq!(linear.amount(15.0), 0.5);
+        assert_eq!(linear.amount(25.0), 1.0);
+
+        let exponential = Fog::exponential(BLACK, 0.1);
+        assert_eq!(exponential.amount(0.0), 0.0);
+        assert!(exponential.amount(10.0) < exponential.amount(20.0));
+
+        let grey = Color::new(0.5, 0.5, 0.5, 1.0);
+        let half = linear.apply(WHITE, 15.0, BlendMode::Alpha);
+        assert_eq!((half.r, half.a), (0.5, 1.0));
+        let fogged = Fog::linear(grey, 10.0, 20.0).apply(WHITE, 25.0, BlendMode::Additive);
+        assert_eq!(fogged.r, 0.0);
+        let faint = Color::new(0.5, 0.5, 0.5, 0.5);
+        let fogged = Fog::linear(WHITE, 10.0, 20.0).apply(faint, 25.0, BlendMode::Premultiplied);
+        assert_eq!((fogged.r, fogged.a), (1.0, 0.5));
+    }
+}
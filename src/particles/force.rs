@@ -0,0 +1,67 @@
+use macroquad::prelude::*;
+
+/// A force acting on every particle of a system, expressed as the
+/// acceleration it produces (particles have unit mass).
+#[derive(Clone, Copy, PartialEq)]
+pub enum Force {
+    /// Constant acceleration, e.g. gravity.
+    Gravity(Vec3),
+    /// Slows particles down in proportion to their speed.
+    Drag(f32),
+    /// Pulls particles toward `position`; negative strength pushes them away.
+    /// Falls off with the inverse square of the distance, softened near the
+    /// centre so it stays finite.
+    Attractor { position: Vec3, strength: f32 },
+}
+
+impl Force {
+    pub fn acceleration(&self, position: Vec3, velocity: Vec3) -> Vec3 {
+        match *self {
+            Force::Gravity(g) => g,
+            Force::Drag(k) => -velocity * k,
+            Force::Attractor {
+                position: center,
+                strength,
+            } => {
+                let offset = center - position;
+                let dist_sq = offset.length_squared() + 1.0;
+                offset * (strength / (dist_sq * dist_sq.sqrt()))
+            }
+        }
+    }
+}
+
+/// Surface particles bounce off.
+#[derive(Clone, Copy)]
+pub enum Collider {
+    /// Infinite plane through `point`; particles are kept on the side
+    /// `normal` points to. `restitution` is the fraction of normal speed kept
+    /// after a bounce.
+    Plane {
+        point: Vec3,
+        normal: Vec3,
+        restitution: f32,
+    },
+}
+
+impl Collider {
+    /// Push `position` back out of the collider and reflect `velocity`.
+    pub fn resolve(&self, position: &mut Vec3, velocity: &mut Vec3) {
+        match *self {
+            Collider::Plane {
+                point,
+                normal,
+                restitution,
+            } => {
+                let depth = (*position - point).dot(normal);
+                if depth < 0.0 {
+                    *position -= normal * depth;
+                    let normal_speed = velocity.dot(normal);
+                    if normal_speed < 0.0 {
+                        *velocity -= normal * normal_speed * (1.0 + restitution);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use macroquad::prelude::*;
+
+/// A light shining in every direction from `position`, fading to nothing at
+/// `range`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Color,
+    pub range: f32,
+}
+
+/// Lights a scene shades mesh particles and billboards with: ambient light,
+/// a directional sun and any number of point lights. Colours act as
+/// intensities, so they may be dimmed by scaling them. Lines are never lit.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Lighting {
+    pub ambient: Color,
+    /// Direction the sunlight travels in.
+    pub sun_direction: Vec3,
+    pub sun_color: Color,
+    pub point_lights: Vec<PointLight>,
+}
+
+impl Lighting {
+    /// Soft ambient light and a white sun from above and slightly behind.
+    pub fn new() -> Self {
+        Self {
+            ambient: Color::new(0.35, 0.35, 0.4, 1.0),
+            sun_direction: vec3(-0.3, -1.0, -0.5).normalize(),
+            sun_color: Color::new(0.7, 0.7, 0.65, 1.0),
+            point_lights: vec![],
+        }
+    }
+
+    /// `color` lit at `position` on a surface facing `normal`. Alpha is left
+    /// alone.
+    pub fn shade(&self, color: Color, position: Vec3, normal: Vec3) -> Color {
+        let mut light =
+            rgb(self.ambient) + rgb(self.sun_color) * normal.dot(-self.sun_direction).max(0.0);
+        for point in &self.point_lights {
+            let offset = point.position - position;
+            let distance = offset.length();
+            if distance >= point.range {
+                continue;
+            }
+            let falloff = 1.0 - distance / point.range;
+            let facing = normal.dot(offset / distance.max(1e-6)).max(0.0);
+            light += rgb(point.color) * facing * falloff * falloff;
+        }
+        Color::new(
+            color.r * light.x,
+            color.g * light.y,
+            color.b * light.z,
+            color.a,
+        )
+    }
+}
+
+fn rgb(color: Color) -> Vec3 {
+    vec3(color.r, color.g, color.b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surfaces_facing_the_light_are_brighter() {
+        let lighting = Lighting {
+            ambient: Color::new(0.2, 0.2, 0.2, 1.0),
+            sun_direction: vec3(0.0, -1.0, 0.0),
+            sun_color: Color::new(0.8, 0.8, 0.8, 1.0),
+            point_lights: vec![],
+        };
+        let up = lighting.shade(WHITE, Vec3::ZERO, Vec3::Y);
+        let down = lighting.shade(WHITE, Vec3::ZERO, Vec3::NEG_Y);
+        assert!((up.r - 1.0).abs() < 1e-6);
+        assert!((down.r - 0.2).abs() < 1e-6);
+
+        let mut lit = lighting.clone();
+        lit.point_lights.push(PointLight {
+            position: vec3(2.0, 0.0, 0.0),
+            color: Color::new(1.0, 0.0, 0.0, 1.0),
+            range: 4.0,
+        });
+        // half way to the edge of its range: a quarter of its strength
+        let side = lit.shade(WHITE, Vec3::ZERO, Vec3::X);
+        assert!((side.r - 0.45).abs() < 1e-6);
+        assert!((side.g - 0.2).abs() < 1e-6);
+        // out of range
+        assert_eq!(
+            lit.shade(WHITE, vec3(-3.0, 0.0, 0.0), Vec3::X),
+            lighting.shade(WHITE, Vec3::ZERO, Vec3::X)
+        );
+    }
+}
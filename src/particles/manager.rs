@@ -1,21 +1,276 @@
-use crate::particles::system::ParticleSystem;
-use macroquad::prelude::*;
+use crate::particles::{render::RenderContext, system::ParticleSystem};
 
-struct ParticleManager {
-    systems: Vec<ParticleSystem>,
+/// Handle to a system owned by a `ParticleManager`.
+///
+/// Handles stay valid while their system is alive and keep pointing at the
+/// same system when others are removed. Once the system is removed the
+/// handle resolves to nothing, even after its slot is reused.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SystemHandle {
+    index: u32,
+    generation: u32,
+}
+
+struct ManagedSystem {
+    name: String,
+    enabled: bool,
+    system: ParticleSystem,
+}
+
+struct Slot {
+    generation: u32,
+    entry: Option<ManagedSystem>,
+}
+
+pub struct ParticleManager {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    /// Live slot indices in insertion order; systems update and draw in this order.
+    order: Vec<u32>,
+    /// Maximum number of particles across all enabled systems.
+    budget: Option<usize>,
+    budget_exceeded: bool,
 }
 
 impl ParticleManager {
     pub fn new() -> Self {
-        Self { systems: vec![] }
+        Self {
+            slots: vec![],
+            free: vec![],
+            order: vec![],
+            budget: None,
+            budget_exceeded: false,
+        }
+    }
+
+    /// Cap the total particle count of all enabled systems. Systems share the
+    /// headroom first come, first served in update order, and apply their own
+    /// overflow policy when they hit their share.
+    pub fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
     }
 
-    pub fn add_system(&mut self, system: ParticleSystem) -> usize {
-        self.systems.push(system);
-        return self.systems.len() - 1;
+    pub fn budget(&self) -> Option<usize> {
+        self.budget
     }
 
-    pub fn remove_system(&mut self, id: usize) {
-        self.systems.swap_remove(id);
+    /// Whether any system had to refuse or kill particles during the last
+    /// update because of the global budget (rather than its own cap).
+    pub fn budget_exceeded(&self) -> bool {
+        self.budget_exceeded
+    }
+
+    /// Total particle count of all enabled systems.
+    pub fn particle_count(&self) -> usize {
+        self.order
+            .iter()
+            .filter_map(|&index| self.slots[index as usize].entry.as_ref())
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.system.particle_count())
+            .sum()
+    }
+
+    pub fn add_system(&mut self, name: impl Into<String>, system: ParticleSystem) -> SystemHandle {
+        let entry = ManagedSystem {
+            name: name.into(),
+            enabled: true,
+            system,
+        };
+
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index as usize].entry = Some(entry);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry: Some(entry),
+                });
+                (self.slots.len() - 1) as u32
+            }
+        };
+        self.order.push(index);
+
+        SystemHandle {
+            index,
+            generation: self.slots[index as usize].generation,
+        }
+    }
+
+    /// Remove a system and hand it back. Returns `None` for stale handles.
+    pub fn remove_system(&mut self, handle: SystemHandle) -> Option<ParticleSystem> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        let entry = slot.entry.take()?;
+        // invalidate every outstanding handle to this slot
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        self.order.retain(|&index| index != handle.index);
+        Some(entry.system)
+    }
+
+    fn entry(&self, handle: SystemHandle) -> Option<&ManagedSystem> {
+        let slot = self.slots.get(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        slot.entry.as_ref()
+    }
+
+    fn entry_mut(&mut self, handle: SystemHandle) -> Option<&mut ManagedSystem> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        slot.entry.as_mut()
+    }
+
+    pub fn get(&self, handle: SystemHandle) -> Option<&ParticleSystem> {
+        self.entry(handle).map(|entry| &entry.system)
+    }
+
+    pub fn get_mut(&mut self, handle: SystemHandle) -> Option<&mut ParticleSystem> {
+        self.entry_mut(handle).map(|entry| &mut entry.system)
+    }
+
+    /// Handle of the first system (in insertion order) called `name`.
+    pub fn find(&self, name: &str) -> Option<SystemHandle> {
+        self.handles()
+            .find(|&handle| self.entry(handle).is_some_and(|entry| entry.name == name))
+    }
+
+    pub fn name(&self, handle: SystemHandle) -> Option<&str> {
+        self.entry(handle).map(|entry| entry.name.as_str())
+    }
+
+    /// Disabled systems are skipped by `update` and `draw` but keep their particles.
+    pub fn set_enabled(&mut self, handle: SystemHandle, enabled: bool) {
+        if let Some(entry) = self.entry_mut(handle) {
+            entry.enabled = enabled;
+        }
+    }
+
+    pub fn is_enabled(&self, handle: SystemHandle) -> bool {
+        self.entry(handle).is_some_and(|entry| entry.enabled)
+    }
+
+    /// Handles of all live systems in insertion order.
+    pub fn handles(&self) -> impl Iterator<Item = SystemHandle> + '_ {
+        self.order.iter().map(|&index| SystemHandle {
+            index,
+            generation: self.slots[index as usize].generation,
+        })
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        let mut total = self.particle_count();
+        self.budget_exceeded = false;
+
+        for &index in &self.order {
+            if let Some(entry) = &mut self.slots[index as usize].entry
+                && entry.enabled
+            {
+                let system = &mut entry.system;
+                let others = total - system.particle_count();
+                let limit = self.budget.map(|budget| budget.saturating_sub(others));
+                system.set_budget_limit(limit);
+                system.update(delta);
+
+                let own_cap = system.max_particle_count().unwrap_or(usize::MAX);
+                if system.dropped_count() > 0 && limit.is_some_and(|limit| limit < own_cap) {
+                    self.budget_exceeded = true;
+                }
+                total = others + system.particle_count();
+            }
+        }
+    }
+
+    pub fn draw(&self, ctx: &RenderContext) {
+        for &index in &self.order {
+            if let Some(entry) = &self.slots[index as usize].entry
+                && entry.enabled
+            {
+                entry.system.draw(ctx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::system::OverflowPolicy;
+    use crate::particles::utils::Spawn;
+    use macroquad::prelude::*;
+
+    fn system() -> ParticleSystem {
+        ParticleSystem::new()
+            .sphere(Vec3::ZERO, 1.0, Spawn::Volume)
+            .spawn_rate(1)
+            .seed(1)
+    }
+
+    #[test]
+    fn handles_survive_other_removals() {
+        let mut manager = ParticleManager::new();
+        let a = manager.add_system("a", system());
+        let b = manager.add_system("b", system());
+        let c = manager.add_system("c", system());
+
+        assert!(manager.remove_system(a).is_some());
+        assert_eq!(manager.name(b), Some("b"));
+        assert_eq!(manager.name(c), Some("c"));
+        assert_eq!(manager.find("c"), Some(c));
+        assert_eq!(manager.find("a"), None);
+    }
+
+    #[test]
+    fn stale_handles_miss_reused_slots() {
+        let mut manager = ParticleManager::new();
+        let a = manager.add_system("a", system());
+        manager.remove_system(a);
+        let d = manager.add_system("d", system());
+
+        assert!(manager.get(a).is_none());
+        assert!(manager.remove_system(a).is_none());
+        assert_eq!(manager.name(d), Some("d"));
+        assert_ne!(a, d);
+    }
+
+    #[test]
+    fn disabled_systems_are_skipped() {
+        let mut manager = ParticleManager::new();
+        let a = manager.add_system("a", system());
+        let b = manager.add_system("b", system());
+        manager.set_enabled(b, false);
+
+        manager.update(0.01);
+        assert_eq!(manager.get(a).unwrap().particle_count(), 1);
+        assert_eq!(manager.get(b).unwrap().particle_count(), 0);
+        assert!(!manager.is_enabled(b));
+        assert_eq!(manager.handles().collect::<Vec<_>>(), vec![a, b]);
+    }
+
+    #[test]
+    fn budget_is_shared_across_systems() {
+        let mut manager = ParticleManager::new();
+        let spawner = || system().spawn_rate(10);
+        let a = manager.add_system("a", spawner());
+        let b = manager.add_system("b", spawner().overflow_policy(OverflowPolicy::KillOldest));
+        manager.set_budget(Some(25));
+
+        manager.update(0.01);
+        assert_eq!(manager.particle_count(), 20);
+        assert!(!manager.budget_exceeded());
+
+        manager.update(0.01);
+        assert_eq!(manager.particle_count(), 25);
+        assert!(manager.budget_exceeded());
+        // `a` updates first and takes the remaining headroom
+        assert_eq!(manager.get(a).unwrap().particle_count(), 15);
+        assert_eq!(manager.get(b).unwrap().particle_count(), 10);
     }
 }
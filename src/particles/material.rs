@@ -0,0 +1,208 @@
+use macroquad::miniquad::{BlendFactor, BlendState, BlendValue, Equation, PrimitiveType};
+use macroquad::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// How a system's particles combine with what's already on screen.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BlendMode {
+    /// Regular transparency; good for smoke and dust.
+    Alpha,
+    /// Adds light on top; good for fire, sparks and glows.
+    Additive,
+    /// For textures with colour already multiplied by alpha; the tint is
+    /// premultiplied in the shader.
+    Premultiplied,
+    /// Darkens what's behind by the particle colour; transparent parts leave
+    /// it untouched.
+    Multiply,
+}
+
+impl BlendMode {
+    /// Whether the result depends on the order particles are drawn in, so
+    /// they need sorting back to front.
+    pub fn is_order_dependent(self) -> bool {
+        matches!(self, BlendMode::Alpha | BlendMode::Premultiplied)
+    }
+}
+
+/// Blending and depth settings a system draws its particles with.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MaterialSettings {
+    pub blend_mode: BlendMode,
+    /// Whether particles occlude what's drawn after them. Usually off for
+    /// translucent effects so they don't cut holes in each other.
+    pub depth_write: bool,
+    /// Whether particles are hidden behind closer geometry.
+    pub depth_test: bool,
+}
+
+impl Default for MaterialSettings {
+    /// What macroquad's default 3D pipeline does.
+    fn default() -> Self {
+        Self {
+            blend_mode: BlendMode::Alpha,
+            depth_write: true,
+            depth_test: true,
+        }
+    }
+}
+
+thread_local! {
+    // materials need the GL context, so they're created on first use on the
+    // render thread and shared by every system drawing with the same settings
+    static MATERIALS: RefCell<HashMap<(MaterialSettings, bool), Material>> =
+        RefCell::new(HashMap::new());
+}
+
+/// The material for `settings`, drawing lines if `lines` is set and triangles
+/// otherwise.
+pub(crate) fn material(settings: MaterialSettings, lines: bool) -> Material {
+    MATERIALS.with(|materials| {
+        materials
+            .borrow_mut()
+            .entry((settings, lines))
+            .or_insert_with(|| create(settings, lines))
+            .clone()
+    })
+}
+
+fn create(settings: MaterialSettings, lines: bool) -> Material {
+    let fragment = match settings.blend_mode {
+        BlendMode::Alpha | BlendMode::Additive => FRAGMENT,
+        BlendMode::Premultiplied => PREMULTIPLIED_FRAGMENT,
+        BlendMode::Multiply => MULTIPLY_FRAGMENT,
+    };
+    load_material(
+        ShaderSource::Glsl {
+            vertex: VERTEX,
+            fragment,
+        },
+        MaterialParams {
+            pipeline_params: pipeline_params(settings, lines),
+            ..Default::default()
+        },
+    )
+    .expect("particle shaders compile")
+}
+
+fn pipeline_params(settings: MaterialSettings, lines: bool) -> PipelineParams {
+    let (source, destination) = match settings.blend_mode {
+        BlendMode::Alpha => (
+            BlendFactor::Value(BlendValue::SourceAlpha),
+            BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+        ),
+        BlendMode::Additive => (
+            BlendFactor::Value(BlendValue::SourceAlpha),
+            BlendFactor::One,
+        ),
+        BlendMode::Premultiplied => (
+            BlendFactor::One,
+            BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+        ),
+        BlendMode::Multiply => (
+            BlendFactor::Value(BlendValue::DestinationColor),
+            BlendFactor::Zero,
+        ),
+    };
+
+    PipelineParams {
+        color_blend: Some(BlendState::new(Equation::Add, source, destination)),
+        depth_write: settings.depth_write,
+        depth_test: if settings.depth_test {
+            Comparison::LessOrEqual
+        } else {
+            Comparison::Always
+        },
+        primitive_type: if lines {
+            PrimitiveType::Lines
+        } else {
+            PrimitiveType::Triangles
+        },
+        ..Default::default()
+    }
+}
+
+// macroquad's default shaders, with fragment variants for the blend modes
+const VERTEX: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}"#;
+
+const FRAGMENT: &str = r#"#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = color * texture2D(Texture, uv);
+}"#;
+
+const PREMULTIPLIED_FRAGMENT: &str = r#"#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = vec4(color.rgb * color.a, color.a) * texture2D(Texture, uv);
+}"#;
+
+const MULTIPLY_FRAGMENT: &str = r#"#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    lowp vec4 c = color * texture2D(Texture, uv);
+    gl_FragColor = vec4(mix(vec3(1.0), c.rgb, c.a), 1.0);
+}"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_map_to_pipeline_state() {
+        let additive = MaterialSettings {
+            blend_mode: BlendMode::Additive,
+            depth_write: false,
+            depth_test: true,
+        };
+        let params = pipeline_params(additive, false);
+        assert_eq!(
+            params.color_blend,
+            Some(BlendState::new(
+                Equation::Add,
+                BlendFactor::Value(BlendValue::SourceAlpha),
+                BlendFactor::One,
+            ))
+        );
+        assert!(!params.depth_write);
+        assert_eq!(params.depth_test, Comparison::LessOrEqual);
+        assert_eq!(params.primitive_type, PrimitiveType::Triangles);
+
+        let overlay = MaterialSettings {
+            depth_test: false,
+            ..MaterialSettings::default()
+        };
+        let params = pipeline_params(overlay, true);
+        assert!(params.depth_write);
+        assert_eq!(params.depth_test, Comparison::Always);
+        assert_eq!(params.primitive_type, PrimitiveType::Lines);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::particles::batch::MAX_INDICES;
+use macroquad::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+/// Triangle mesh drawn in place of each particle by `ParticleStyle::Mesh`,
+/// centred on the origin with a radius of about 0.5 so the particle's size
+/// scales it like a billboard.
+///
+/// Build it once and share it between systems; every frame only copies it
+/// into the batch.
+pub struct ParticleMesh {
+    pub(crate) positions: Vec<Vec3>,
+    pub(crate) normals: Vec<Vec3>,
+    pub(crate) indices: Vec<u16>,
+}
+
+impl ParticleMesh {
+    /// A unit cube with a normal per face.
+    pub fn cube() -> Self {
+        let mut mesh = Self {
+            positions: vec![],
+            normals: vec![],
+            indices: vec![],
+        };
+        for normal in [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ] {
+            // two axes spanning the face, ordered so triangles wind outwards
+            let u = normal.any_orthonormal_vector();
+            let v = normal.cross(u);
+            let first = mesh.positions.len() as u16;
+            for (a, b) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                mesh.positions.push((normal + u * a + v * b) * 0.5);
+                mesh.normals.push(normal);
+            }
+            mesh.indices
+                .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+        mesh
+    }
+
+    /// A UV sphere of diameter 1 with `rings` bands of `segments` quads.
+    /// Both must be non-zero, and small enough that the sphere fits in
+    /// `MAX_INDICES` indices.
+    pub fn sphere(rings: u16, segments: u16) -> Self {
+        assert!(
+            rings > 0 && segments > 0,
+            "sphere needs at least one ring and segment, got {rings} × {segments}"
+        );
+        let index_count = rings as usize * segments as usize * 6;
+        assert!(
+            index_count <= MAX_INDICES,
+            "sphere with {rings} × {segments} quads needs {index_count} indices, more than {MAX_INDICES}"
+        );
+        let mut mesh = Self {
+            positions: vec![],
+            normals: vec![],
+            indices: vec![],
+        };
+        for ring in 0..=rings {
+            let (sin_theta, cos_theta) = (ring as f32 / rings as f32 * PI).sin_cos();
+            for segment in 0..=segments {
+                let (sin_phi, cos_phi) = (segment as f32 / segments as f32 * TAU).sin_cos();
+                let normal = vec3(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi);
+                mesh.positions.push(normal * 0.5);
+                mesh.normals.push(normal);
+            }
+        }
+        let row = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = ring * row + segment;
+                let b = a + row;
+                mesh.indices.extend([a, a + 1, b, a + 1, b + 1, b]);
+            }
+        }
+        mesh
+    }
+
+    /// Parse the vertices and faces of a Wavefront OBJ file. Polygons are
+    /// split into triangles and normals are averaged from the faces around
+    /// each vertex; texture coordinates, normals and materials in the file
+    /// are ignored.
+    pub fn from_obj(source: &str) -> Result<Self, String> {
+        let mut positions = vec![];
+        let mut indices: Vec<u16> = vec![];
+        for (number, line) in source.lines().enumerate() {
+            let error = |message: &str| format!("line {}: {message}", number + 1);
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("v") => {
+                    let mut coordinate = || -> Result<f32, String> {
+                        let word = words.next().ok_or_else(|| error("missing coordinate"))?;
+                        word.parse().map_err(|_| error("bad coordinate"))
+                    };
+                    positions.push(vec3(coordinate()?, coordinate()?, coordinate()?));
+                }
+                Some("f") => {
+                    let mut corners = vec![];
+                    for word in words {
+                        // `v`, `v/vt`, `v//vn` or `v/vt/vn`; negative indices count back
+                        let index: i64 = word
+                            .split('/')
+                            .next()
+                            .and_then(|index| index.parse().ok())
+                            .ok_or_else(|| error("bad face index"))?;
+                        let index = if index < 0 {
+                            positions.len() as i64 + index
+                        } else {
+                            index - 1
+                        };
+                        if index < 0 || index >= positions.len() as i64 {
+                            return Err(error("face index out of range"));
+                        }
+                        corners.push(index as u16);
+                    }
+                    if corners.len() < 3 {
+                        return Err(error("face with fewer than three corners"));
+                    }
+                    for i in 1..corners.len() - 1 {
+                        indices.extend([corners[0], corners[i], corners[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if indices.is_empty() {
+            return Err("no faces".to_string());
+        }
+        if positions.len() > MAX_INDICES || indices.len() > MAX_INDICES {
+            return Err(format!(
+                "more than {MAX_INDICES} vertices or indices; too detailed for a particle"
+            ));
+        }
+
+        let mut normals = vec![Vec3::ZERO; positions.len()];
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+            // weighted by area, so slivers barely count
+            let normal = (b - a).cross(c - a);
+            for &index in triangle {
+                normals[index as usize] += normal;
+            }
+        }
+        for normal in &mut normals {
+            *normal = normal.normalize_or_zero();
+        }
+
+        Ok(Self {
+            positions,
+            normals,
+            indices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_meshes_wind_outwards() {
+        for mesh in [ParticleMesh::cube(), ParticleMesh::sphere(6, 8)] {
+            for triangle in mesh.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[triangle[i] as usize]);
+                let normal = (b - a).cross(c - a);
+                if normal.length() > 1e-6 {
+                    assert!(normal.dot(a + b + c) > 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn obj_polygons_become_triangles() {
+        let quad = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1 -1//1\n";
+        let mesh = ParticleMesh::from_obj(quad).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert!(mesh.normals.iter().all(|n| *n == Vec3::Z));
+
+        assert_eq!(
+            ParticleMesh::from_obj("v 0 0 0\nf 1 2 3").err().unwrap(),
+            "line 2: face index out of range"
+        );
+    }
+}
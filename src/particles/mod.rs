@@ -1,5 +1,24 @@
+pub mod batch;
+pub mod colormap;
 pub mod emitter;
+pub mod field;
+pub mod flipbook;
+pub mod fog;
+pub mod force;
+pub mod lighting;
 pub mod particle;
 pub mod manager;
+pub mod material;
+pub mod mesh;
+pub mod passes;
+pub mod path;
+pub mod render;
+pub mod sampling;
+pub mod simd;
+pub mod shadow;
+pub mod simulation;
+pub mod sort;
+pub mod storage;
 pub mod system;
+pub mod trail;
 pub mod utils;
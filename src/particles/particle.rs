@@ -1,9 +1,34 @@
 use macroquad::prelude::*;
 
+#[derive(Clone, Copy)]
 pub struct Particle {
     pub position: Vec3,
     pub prev_position: Vec3,
     pub velocity: Vec3,
     pub energy: f32,
     pub size: f32,
+    /// Screen-space rotation of the particle's billboard, in radians.
+    pub rotation: f32,
+    /// Random number in [0, 1) picked at spawn, for varying the look of
+    /// otherwise identical particles, e.g. a flipbook's start frame.
+    pub variation: f32,
+    /// Seconds since the particle was spawned.
+    pub age: f32,
+}
+
+#[cfg(test)]
+impl Particle {
+    /// A newborn particle at `position` moving with `velocity`, for tests.
+    pub(crate) fn at(position: Vec3, velocity: Vec3) -> Self {
+        Self {
+            position,
+            prev_position: position,
+            velocity,
+            energy: 1.0,
+            size: 0.1,
+            rotation: 0.0,
+            variation: 0.0,
+            age: 0.0,
+        }
+    }
 }
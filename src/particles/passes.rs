@@ -0,0 +1,353 @@
+//! The per-particle passes of `ParticleSystem::update`.
+//!
+//! Each pass is a serial kernel over a chunk of attribute slices; the
+//! integration and force passes have a scalar and a vectorised (`simd`)
+//! implementation, selected with `Kernel`.
+//!
+//! With the `parallel` feature, large systems split the slices into chunks
+//! that rayon processes concurrently; every particle is handled
+//! independently, so the result is the same bit for bit whichever way it
+//! runs.
+
+use crate::particles::{
+    force::{Collider, Force},
+    simd,
+    storage::ParticleAttributesMut,
+};
+use macroquad::prelude::*;
+use std::ops::Range;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Below this many particles the passes stay serial; splitting the work
+/// costs more than it saves.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 8192;
+#[cfg(feature = "parallel")]
+const CHUNK: usize = 2048;
+
+/// Implementation used by the integration and force passes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kernel {
+    /// One particle at a time with glam vector math.
+    Scalar,
+    /// Several particles at a time: each chunk of `Vec3`s is copied into
+    /// per-component lanes, updated there and copied back. The default; in
+    /// release builds it runs the update passes about twice as fast, as
+    /// `cargo test --release kernel_timings -- --ignored --nocapture` shows.
+    Simd,
+}
+
+/// Run every pass over all particles: move them with their current
+/// velocity, then accelerate them, then resolve collisions.
+pub(crate) fn step_all(
+    attrs: &mut ParticleAttributesMut,
+    forces: &[Force],
+    colliders: &[Collider],
+    delta: f32,
+    kernel: Kernel,
+) {
+    integrate(attrs, delta, kernel);
+    apply_forces(attrs, forces, delta, kernel);
+    collide(attrs, colliders);
+}
+
+/// Run every pass serially over the particles in `range` only. Ranges are
+/// short (newborns are advanced one at a time), so this is always scalar.
+pub(crate) fn step_range(
+    attrs: &mut ParticleAttributesMut,
+    range: Range<usize>,
+    forces: &[Force],
+    colliders: &[Collider],
+    delta: f32,
+) {
+    integrate_scalar(
+        &mut attrs.positions[range.clone()],
+        &mut attrs.prev_positions[range.clone()],
+        &attrs.velocities[range.clone()],
+        &mut attrs.energies[range.clone()],
+        &mut attrs.ages[range.clone()],
+        delta,
+    );
+    forces_scalar(
+        &mut attrs.velocities[range.clone()],
+        &attrs.positions[range.clone()],
+        forces,
+        delta,
+    );
+    collide_chunk(
+        &mut attrs.positions[range.clone()],
+        &mut attrs.velocities[range],
+        colliders,
+    );
+}
+
+fn apply_forces(attrs: &mut ParticleAttributesMut, forces: &[Force], delta: f32, kernel: Kernel) {
+    if forces.is_empty() {
+        return;
+    }
+
+    #[cfg(feature = "parallel")]
+    if attrs.velocities.len() >= PARALLEL_THRESHOLD {
+        attrs
+            .velocities
+            .par_chunks_mut(CHUNK)
+            .zip(attrs.positions.par_chunks(CHUNK))
+            .for_each(|(velocities, positions)| {
+                forces_chunk(velocities, positions, forces, delta, kernel)
+            });
+        return;
+    }
+
+    forces_chunk(attrs.velocities, attrs.positions, forces, delta, kernel);
+}
+
+fn integrate(attrs: &mut ParticleAttributesMut, delta: f32, kernel: Kernel) {
+    #[cfg(feature = "parallel")]
+    if attrs.positions.len() >= PARALLEL_THRESHOLD {
+        attrs
+            .positions
+            .par_chunks_mut(CHUNK)
+            .zip(attrs.prev_positions.par_chunks_mut(CHUNK))
+            .zip(attrs.velocities.par_chunks(CHUNK))
+            .zip(attrs.energies.par_chunks_mut(CHUNK))
+            .zip(attrs.ages.par_chunks_mut(CHUNK))
+            .for_each(|((((positions, prev), velocities), energies), ages)| {
+                integrate_chunk(positions, prev, velocities, energies, ages, delta, kernel)
+            });
+        return;
+    }
+
+    integrate_chunk(
+        attrs.positions,
+        attrs.prev_positions,
+        attrs.velocities,
+        attrs.energies,
+        attrs.ages,
+        delta,
+        kernel,
+    );
+}
+
+fn collide(attrs: &mut ParticleAttributesMut, colliders: &[Collider]) {
+    if colliders.is_empty() {
+        return;
+    }
+
+    #[cfg(feature = "parallel")]
+    if attrs.positions.len() >= PARALLEL_THRESHOLD {
+        attrs
+            .positions
+            .par_chunks_mut(CHUNK)
+            .zip(attrs.velocities.par_chunks_mut(CHUNK))
+            .for_each(|(positions, velocities)| collide_chunk(positions, velocities, colliders));
+        return;
+    }
+
+    collide_chunk(attrs.positions, attrs.velocities, colliders);
+}
+
+fn forces_chunk(
+    velocities: &mut [Vec3],
+    positions: &[Vec3],
+    forces: &[Force],
+    delta: f32,
+    kernel: Kernel,
+) {
+    match kernel {
+        Kernel::Scalar => forces_scalar(velocities, positions, forces, delta),
+        Kernel::Simd => simd::apply_forces(velocities, positions, forces, delta),
+    }
+}
+
+fn integrate_chunk(
+    positions: &mut [Vec3],
+    prev_positions: &mut [Vec3],
+    velocities: &[Vec3],
+    energies: &mut [f32],
+    ages: &mut [f32],
+    delta: f32,
+    kernel: Kernel,
+) {
+    let integrate = match kernel {
+        Kernel::Scalar => integrate_scalar,
+        Kernel::Simd => simd::integrate,
+    };
+    integrate(positions, prev_positions, velocities, energies, ages, delta);
+}
+
+pub(crate) fn forces_scalar(
+    velocities: &mut [Vec3],
+    positions: &[Vec3],
+    forces: &[Force],
+    delta: f32,
+) {
+    for (velocity, &position) in velocities.iter_mut().zip(positions) {
+        let mut acceleration = Vec3::ZERO;
+        for force in forces {
+            acceleration += force.acceleration(position, *velocity);
+        }
+        *velocity += acceleration * delta;
+    }
+}
+
+pub(crate) fn integrate_scalar(
+    positions: &mut [Vec3],
+    prev_positions: &mut [Vec3],
+    velocities: &[Vec3],
+    energies: &mut [f32],
+    ages: &mut [f32],
+    delta: f32,
+) {
+    prev_positions.copy_from_slice(positions);
+    for (position, &velocity) in positions.iter_mut().zip(velocities) {
+        *position += velocity * delta;
+    }
+    for energy in energies.iter_mut() {
+        *energy -= delta; // reduction of timespan overtime
+    }
+    for age in ages.iter_mut() {
+        *age += delta;
+    }
+}
+
+fn collide_chunk(positions: &mut [Vec3], velocities: &mut [Vec3], colliders: &[Collider]) {
+    for (position, velocity) in positions.iter_mut().zip(velocities.iter_mut()) {
+        for collider in colliders {
+            collider.resolve(position, velocity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::{particle::Particle, storage::ParticleStorage};
+
+    fn storage(count: usize) -> ParticleStorage {
+        let mut storage = ParticleStorage::new();
+        for i in 0..count {
+            let f = i as f32 * 0.001;
+            storage.push(Particle::at(
+                vec3(f.sin() * 4.0, f.cos() * 3.0, f),
+                vec3(f.cos(), -f.sin(), 1.0 - f),
+            ));
+        }
+        storage
+    }
+
+    const FORCES: [Force; 3] = [
+        Force::Gravity(vec3(0.0, -9.8, 0.0)),
+        Force::Drag(0.3),
+        Force::Attractor {
+            position: vec3(1.0, 2.0, 3.0),
+            strength: 5.0,
+        },
+    ];
+    const COLLIDERS: [Collider; 1] = [Collider::Plane {
+        point: vec3(0.0, -1.0, 0.0),
+        normal: vec3(0.0, 1.0, 0.0),
+        restitution: 0.5,
+    }];
+
+    #[test]
+    fn chunked_passes_match_single_range() {
+        // large enough to take the parallel path when the feature is enabled
+        let count = 20_000;
+        let mut whole = storage(count);
+        let mut ranged = storage(count);
+        for _ in 0..10 {
+            step_all(
+                &mut whole.attributes_mut(),
+                &FORCES,
+                &COLLIDERS,
+                0.016,
+                Kernel::Scalar,
+            );
+            step_range(
+                &mut ranged.attributes_mut(),
+                0..count,
+                &FORCES,
+                &COLLIDERS,
+                0.016,
+            );
+        }
+        for (a, b) in whole.iter().zip(ranged.iter()) {
+            assert_eq!(
+                a.position.to_array().map(f32::to_bits),
+                b.position.to_array().map(f32::to_bits)
+            );
+            assert_eq!(
+                a.velocity.to_array().map(f32::to_bits),
+                b.velocity.to_array().map(f32::to_bits)
+            );
+        }
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        // not a multiple of the lane count, so the scalar tail runs too
+        let count = 1003;
+        let mut scalar = storage(count);
+        let mut simd = storage(count);
+        for _ in 0..50 {
+            step_all(
+                &mut scalar.attributes_mut(),
+                &FORCES,
+                &COLLIDERS,
+                0.016,
+                Kernel::Scalar,
+            );
+            step_all(
+                &mut simd.attributes_mut(),
+                &FORCES,
+                &COLLIDERS,
+                0.016,
+                Kernel::Simd,
+            );
+        }
+        for (a, b) in scalar.iter().zip(simd.iter()) {
+            assert!(a.position.abs_diff_eq(b.position, 1e-4));
+            assert!(a.prev_position.abs_diff_eq(b.prev_position, 1e-4));
+            assert!(a.velocity.abs_diff_eq(b.velocity, 1e-4));
+            assert!((a.energy - b.energy).abs() < 1e-6);
+            assert!((a.age - b.age).abs() < 1e-6);
+        }
+    }
+
+    /// Times both kernels over the same particles; run it in release with
+    /// `--ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn kernel_timings() {
+        let (count, steps) = (100_000, 200);
+        for kernel in [Kernel::Scalar, Kernel::Simd] {
+            let mut particles = storage(count);
+            let start = std::time::Instant::now();
+            for _ in 0..steps {
+                step_all(&mut particles.attributes_mut(), &FORCES, &[], 0.016, kernel);
+            }
+            println!(
+                "{kernel:?}: {count} particles x {steps} steps in {:?}",
+                start.elapsed()
+            );
+        }
+    }
+
+    #[test]
+    fn plane_collider_bounces() {
+        let mut storage = storage(0);
+        storage.push(Particle::at(vec3(0.0, -0.9, 0.0), vec3(1.0, -10.0, 0.0)));
+        step_all(
+            &mut storage.attributes_mut(),
+            &[],
+            &COLLIDERS,
+            0.1,
+            Kernel::Scalar,
+        );
+        let p = storage.get(0);
+        assert!((p.position.y + 1.0).abs() < 1e-6);
+        assert_eq!(p.velocity, vec3(1.0, 5.0, 0.0));
+    }
+}
@@ -0,0 +1,308 @@
+use macroquad::prelude::*;
+
+/// A pose on a motion path at a given time (in seconds from the path start).
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: f32,
+    pub position: Vec3,
+    pub rotation: Quat,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, position: Vec3) -> Self {
+        Self {
+            time,
+            position,
+            rotation: Quat::IDENTITY,
+        }
+    }
+
+    pub fn rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Interpolation {
+    /// Straight segments between consecutive keyframes.
+    Linear,
+    /// Smooth curve through every keyframe.
+    CatmullRom,
+    /// Cubic Bézier segments. Keyframes are read as anchor, handle, handle,
+    /// anchor, handle, handle, anchor, ...; the time and rotation of the
+    /// handle keyframes are ignored.
+    Bezier,
+}
+
+/// What happens when the path time runs past the last keyframe.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PathMode {
+    /// Stop at the last keyframe.
+    Once,
+    /// Jump back to the first keyframe.
+    Loop,
+    /// Travel back and forth between the first and last keyframe.
+    PingPong,
+}
+
+/// Keyframed motion for an emitter's position and rotation.
+#[derive(Clone)]
+pub struct MotionPath {
+    keyframes: Vec<Keyframe>,
+    interpolation: Interpolation,
+    mode: PathMode,
+    speed: f32,
+    time: f32,
+}
+
+impl MotionPath {
+    pub fn linear(keyframes: Vec<Keyframe>) -> Self {
+        Self::new(keyframes, Interpolation::Linear)
+    }
+
+    pub fn catmull_rom(keyframes: Vec<Keyframe>) -> Self {
+        Self::new(keyframes, Interpolation::CatmullRom)
+    }
+
+    /// `keyframes.len()` must be `3 * segments + 1`.
+    pub fn bezier(keyframes: Vec<Keyframe>) -> Self {
+        assert!(
+            keyframes.len() % 3 == 1,
+            "bezier path needs 3n + 1 keyframes, got {}",
+            keyframes.len()
+        );
+        Self::new(keyframes, Interpolation::Bezier)
+    }
+
+    fn new(keyframes: Vec<Keyframe>, interpolation: Interpolation) -> Self {
+        assert!(
+            !keyframes.is_empty(),
+            "motion path needs at least one keyframe"
+        );
+        Self {
+            keyframes,
+            interpolation,
+            mode: PathMode::Loop,
+            speed: 1.0,
+            time: 0.0,
+        }
+    }
+
+    pub fn mode(mut self, mode: PathMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Playback speed multiplier; 1.0 plays the keyframe times as written.
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// Time of the last keyframe (or last anchor for Bézier paths).
+    pub fn duration(&self) -> f32 {
+        self.keyframes[self.keyframes.len() - 1].time
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        self.time += delta * self.speed;
+    }
+
+    /// Position and rotation at the current playback time.
+    pub fn current(&self) -> (Vec3, Quat) {
+        self.sample(self.time)
+    }
+
+    /// Position and rotation at `time`, with the path mode applied.
+    pub fn sample(&self, time: f32) -> (Vec3, Quat) {
+        let start = self.keyframes[0].time;
+        let local = self.wrap_time(time - start) + start;
+
+        let step = match self.interpolation {
+            Interpolation::Bezier => 3,
+            _ => 1,
+        };
+        let last = self.keyframes.len() - 1;
+        if last == 0 {
+            let k = &self.keyframes[0];
+            return (k.position, k.rotation);
+        }
+
+        // find the segment [i, i + step] containing `local`
+        let mut i = 0;
+        while i + step < last && self.keyframes[i + step].time < local {
+            i += step;
+        }
+        let a = &self.keyframes[i];
+        let b = &self.keyframes[i + step];
+        let span = b.time - a.time;
+        let u = if span > 0.0 {
+            ((local - a.time) / span).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        let position = match self.interpolation {
+            Interpolation::Linear => a.position.lerp(b.position, u),
+            Interpolation::CatmullRom => {
+                let p0 = self.keyframes[i.saturating_sub(1)].position;
+                let p3 = self.keyframes[(i + 2).min(last)].position;
+                catmull_rom(p0, a.position, b.position, p3, u)
+            }
+            Interpolation::Bezier => bezier(
+                a.position,
+                self.keyframes[i + 1].position,
+                self.keyframes[i + 2].position,
+                b.position,
+                u,
+            ),
+        };
+
+        (position, a.rotation.slerp(b.rotation, u))
+    }
+
+    fn wrap_time(&self, time: f32) -> f32 {
+        let duration = self.duration() - self.keyframes[0].time;
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        match self.mode {
+            PathMode::Once => time.clamp(0.0, duration),
+            PathMode::Loop => time.rem_euclid(duration),
+            PathMode::PingPong => {
+                let t = time.rem_euclid(duration * 2.0);
+                if t > duration { duration * 2.0 - t } else { t }
+            }
+        }
+    }
+
+    /// Draw the path as a polyline in world space. Call with a 3D camera set.
+    pub fn draw(&self, color: Color) {
+        let start = self.keyframes[0].time;
+        let duration = self.duration() - start;
+        let segments = 32 * self.keyframes.len();
+        let mut prev = self.sample_unwrapped(start);
+        for s in 1..=segments {
+            let next = self.sample_unwrapped(start + duration * s as f32 / segments as f32);
+            draw_line_3d(prev, next, color);
+            prev = next;
+        }
+    }
+
+    // Sample a time already inside [start, end] so the polyline covers the
+    // whole path regardless of the playback mode.
+    fn sample_unwrapped(&self, time: f32) -> Vec3 {
+        let end = self.duration();
+        if time >= end {
+            return self.keyframes[self.keyframes.len() - 1].position;
+        }
+        self.sample(time).0
+    }
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+fn bezier(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let u = 1.0 - t;
+    p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframes() -> Vec<Keyframe> {
+        vec![
+            Keyframe::new(0.0, vec3(0.0, 0.0, 0.0)),
+            Keyframe::new(1.0, vec3(2.0, 0.0, 0.0)).rotation(Quat::from_rotation_y(1.0)),
+            Keyframe::new(3.0, vec3(2.0, 4.0, 0.0)),
+            Keyframe::new(4.0, vec3(0.0, 4.0, 2.0)),
+        ]
+    }
+
+    #[test]
+    fn paths_pass_through_their_keyframes() {
+        let keys = keyframes();
+        for path in [
+            MotionPath::linear(keys.clone()),
+            MotionPath::catmull_rom(keys.clone()),
+        ] {
+            let path = path.mode(PathMode::Once);
+            for key in &keys {
+                let (position, rotation) = path.sample(key.time);
+                assert!(position.abs_diff_eq(key.position, 1e-5), "{position}");
+                assert!(rotation.abs_diff_eq(key.rotation, 1e-5));
+            }
+        }
+        let halfway = MotionPath::linear(keys.clone()).sample(2.0).0;
+        assert!(halfway.abs_diff_eq(vec3(2.0, 2.0, 0.0), 1e-5));
+
+        // Bézier curves pass through the anchors only
+        let bezier = MotionPath::bezier(keys.clone()).mode(PathMode::Once);
+        assert!(bezier.sample(0.0).0.abs_diff_eq(keys[0].position, 1e-5));
+        assert!(bezier.sample(4.0).0.abs_diff_eq(keys[3].position, 1e-5));
+        let middle = bezier.sample(2.0).0;
+        let expected =
+            (keys[0].position + 3.0 * keys[1].position + 3.0 * keys[2].position + keys[3].position)
+                / 8.0;
+        assert!(middle.abs_diff_eq(expected, 1e-5), "{middle}");
+    }
+
+    #[test]
+    fn modes_wrap_time_past_the_ends() {
+        let path = MotionPath::linear(keyframes());
+        let once = path.clone().mode(PathMode::Once);
+        assert_eq!(once.wrap_time(5.5), 4.0);
+        assert_eq!(once.wrap_time(-1.0), 0.0);
+        assert_eq!(once.wrap_time(2.5), 2.5);
+
+        let looping = path.clone().mode(PathMode::Loop);
+        assert_eq!(looping.wrap_time(5.5), 1.5);
+        assert_eq!(looping.wrap_time(-1.0), 3.0);
+        assert_eq!(looping.wrap_time(4.0), 0.0);
+
+        let ping_pong = path.mode(PathMode::PingPong);
+        assert_eq!(ping_pong.wrap_time(5.5), 2.5);
+        assert_eq!(ping_pong.wrap_time(9.0), 1.0);
+        assert_eq!(ping_pong.wrap_time(-1.0), 1.0);
+        assert_eq!(ping_pong.wrap_time(4.0), 4.0);
+
+        // sampling applies the mode relative to the first keyframe's time
+        let late = MotionPath::linear(vec![
+            Keyframe::new(10.0, Vec3::ZERO),
+            Keyframe::new(12.0, vec3(4.0, 0.0, 0.0)),
+        ]);
+        assert!(late.sample(13.0).0.abs_diff_eq(vec3(2.0, 0.0, 0.0), 1e-5));
+        assert!(late.sample(9.0).0.abs_diff_eq(vec3(2.0, 0.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn single_keyframe_paths_stand_still() {
+        let rotation = Quat::from_rotation_x(0.5);
+        let key = Keyframe::new(2.0, vec3(1.0, 2.0, 3.0)).rotation(rotation);
+        for mode in [PathMode::Once, PathMode::Loop, PathMode::PingPong] {
+            let mut path = MotionPath::catmull_rom(vec![key]).mode(mode);
+            assert_eq!(path.duration(), 2.0);
+            assert_eq!(path.wrap_time(7.0), 0.0);
+            for time in [-3.0, 0.0, 2.0, 50.0] {
+                assert_eq!(path.sample(time), (key.position, rotation));
+            }
+            path.advance(10.0);
+            assert_eq!(path.current(), (key.position, rotation));
+        }
+        let (position, _) = MotionPath::bezier(vec![key]).sample(1.0);
+        assert_eq!(position, key.position);
+    }
+}
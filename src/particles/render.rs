@@ -0,0 +1,40 @@
+use crate::particles::{fog::Fog, lighting::Lighting};
+use macroquad::prelude::*;
+
+/// Per-frame view information systems need to draw camera-dependent
+/// geometry such as billboards.
+pub struct RenderContext<'a> {
+    /// Camera basis in world space: screen right and screen up.
+    pub right: Vec3,
+    pub up: Vec3,
+    pub camera_position: Vec3,
+    /// Lights to shade meshes and billboards with; unlit if `None`.
+    pub lighting: Option<&'a Lighting>,
+    /// Fog to blend everything drawn towards with distance.
+    pub fog: Option<Fog>,
+}
+
+impl<'a> RenderContext<'a> {
+    pub fn from_camera(camera: &Camera3D) -> Self {
+        let forward = (camera.target - camera.position).normalize_or_zero();
+        let right = forward.cross(camera.up).normalize_or_zero();
+        let up = right.cross(forward);
+        Self {
+            right,
+            up,
+            camera_position: camera.position,
+            lighting: None,
+            fog: None,
+        }
+    }
+
+    pub fn with_lighting(mut self, lighting: &'a Lighting) -> Self {
+        self.lighting = Some(lighting);
+        self
+    }
+
+    pub fn with_fog(mut self, fog: Fog) -> Self {
+        self.fog = Some(fog);
+        self
+    }
+}
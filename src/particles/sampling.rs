@@ -0,0 +1,246 @@
+use macroquad::prelude::*;
+use macroquad::rand::RandGenerator;
+use std::f32::consts::TAU;
+
+/// Uniformly distributed unit vector.
+pub fn uniform_on_sphere(rng: &RandGenerator) -> Vec3 {
+    // Archimedes: z is uniform on [-1, 1] for a uniform point on the sphere
+    let z = rng.gen_range(-1.0f32, 1.0f32);
+    let phi = rng.gen_range(0.0, TAU);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Uniformly distributed point inside a ball of the given radius.
+pub fn uniform_in_ball(rng: &RandGenerator, radius: f32) -> Vec3 {
+    let r = rng.gen_range(0.0f32, 1.0f32).cbrt() * radius;
+    uniform_on_sphere(rng) * r
+}
+
+/// Unit vector uniformly distributed over the spherical cap around `axis`
+/// with the given half-angle (radians). A half-angle of PI covers the sphere.
+pub fn uniform_in_cone(rng: &RandGenerator, axis: Vec3, half_angle: f32) -> Vec3 {
+    let cos_max = half_angle.clamp(0.0, std::f32::consts::PI).cos();
+    let z = rng.gen_range(cos_max, 1.0);
+    let phi = rng.gen_range(0.0, TAU);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    align_to(axis, vec3(r * phi.cos(), r * phi.sin(), z))
+}
+
+/// Unit vector in the hemisphere around `normal`, with density proportional
+/// to the cosine of the angle to the normal.
+pub fn cosine_hemisphere(rng: &RandGenerator, normal: Vec3) -> Vec3 {
+    // Malley's method: uniform point on the disk projected up to the hemisphere
+    let u = rng.gen_range(0.0f32, 1.0f32);
+    let phi = rng.gen_range(0.0, TAU);
+    let r = u.sqrt();
+    let z = (1.0 - u).max(0.0).sqrt();
+    align_to(normal, vec3(r * phi.cos(), r * phi.sin(), z))
+}
+
+/// Unit vector drawn from stratum `index` of `count` equal-area bands of the
+/// sphere. Taking one sample per stratum for `index` in `0..count` covers the
+/// sphere evenly while staying random.
+pub fn stratified_on_sphere(rng: &RandGenerator, index: usize, count: usize) -> Vec3 {
+    let count = count.max(1) as f32;
+    let jitter = rng.gen_range(0.0f32, 1.0f32);
+    let z = -1.0 + 2.0 * (index as f32 + jitter) / count;
+    let phi = rng.gen_range(0.0, TAU);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Dart-throwing Poisson-disk sampling: fills `out` with `count` points drawn
+/// from `sample`, rejecting candidates closer than `min_distance` to an
+/// accepted point. After `attempts` rejections in a row the last candidate is
+/// accepted anyway, so `out` always ends up with `count` points.
+pub fn poisson_disk(
+    out: &mut Vec<Vec3>,
+    count: usize,
+    min_distance: f32,
+    attempts: usize,
+    mut sample: impl FnMut() -> Vec3,
+) {
+    out.clear();
+    let min_sq = min_distance * min_distance;
+    while out.len() < count {
+        let mut candidate = sample();
+        for _ in 0..attempts {
+            if out.iter().all(|p| p.distance_squared(candidate) >= min_sq) {
+                break;
+            }
+            candidate = sample();
+        }
+        out.push(candidate);
+    }
+}
+
+/// Rotate `v`, expressed in a frame where +Z is the axis, onto `axis`.
+fn align_to(axis: Vec3, v: Vec3) -> Vec3 {
+    let axis = axis.normalize_or_zero();
+    if axis == Vec3::ZERO {
+        return v;
+    }
+    Quat::from_rotation_arc(Vec3::Z, axis) * v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 20_000;
+
+    fn seeded(seed: u64) -> RandGenerator {
+        let rng = RandGenerator::new();
+        rng.srand(seed);
+        rng
+    }
+
+    /// Pearson chi-squared statistic of `counts` against a uniform expectation.
+    fn chi_squared(counts: &[usize]) -> f32 {
+        let total: usize = counts.iter().sum();
+        let expected = total as f32 / counts.len() as f32;
+        counts
+            .iter()
+            .map(|&c| (c as f32 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    // 99.9th percentile of chi-squared with 9 degrees of freedom (10 bins)
+    const CHI2_9_DOF: f32 = 27.88;
+    // ... and with 7 degrees of freedom (8 bins)
+    const CHI2_7_DOF: f32 = 24.32;
+
+    fn histogram(values: impl Iterator<Item = f32>, min: f32, max: f32, bins: usize) -> Vec<usize> {
+        let mut counts = vec![0; bins];
+        for v in values {
+            let i = (((v - min) / (max - min)) * bins as f32) as usize;
+            counts[i.min(bins - 1)] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn sphere_is_uniform() {
+        let rng = seeded(1);
+        let dirs: Vec<Vec3> = (0..SAMPLES).map(|_| uniform_on_sphere(&rng)).collect();
+        assert!(dirs.iter().all(|d| (d.length() - 1.0).abs() < 1e-4));
+
+        // every axis projection of a uniform sphere point is uniform on [-1, 1]
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let counts = histogram(dirs.iter().map(|d| d.dot(axis)), -1.0, 1.0, 10);
+            assert!(chi_squared(&counts) < CHI2_9_DOF, "{axis}: {counts:?}");
+        }
+
+        // octants (the cube-corner bias shows up here)
+        let mut octants = [0usize; 8];
+        for d in &dirs {
+            let i =
+                (d.x > 0.0) as usize | ((d.y > 0.0) as usize) << 1 | ((d.z > 0.0) as usize) << 2;
+            octants[i] += 1;
+        }
+        assert!(chi_squared(&octants) < CHI2_7_DOF, "{octants:?}");
+
+        // direction along the cube diagonal should be no more likely than along an axis
+        let near = |target: Vec3| dirs.iter().filter(|d| d.dot(target) > 0.95).count() as f32;
+        let diagonal = near(vec3(1.0, 1.0, 1.0).normalize());
+        let axis = near(Vec3::X);
+        assert!((diagonal / axis - 1.0).abs() < 0.25, "{diagonal} vs {axis}");
+    }
+
+    #[test]
+    fn ball_is_uniform() {
+        let rng = seeded(2);
+        let radius = 3.0;
+        let points: Vec<Vec3> = (0..SAMPLES)
+            .map(|_| uniform_in_ball(&rng, radius))
+            .collect();
+        assert!(points.iter().all(|p| p.length() <= radius + 1e-4));
+        // (r / R)^3 is uniform on [0, 1] for a uniform ball
+        let counts = histogram(
+            points.iter().map(|p| (p.length() / radius).powi(3)),
+            0.0,
+            1.0,
+            10,
+        );
+        assert!(chi_squared(&counts) < CHI2_9_DOF, "{counts:?}");
+    }
+
+    #[test]
+    fn cone_is_uniform_over_cap() {
+        let rng = seeded(3);
+        let axis = vec3(0.3, -1.0, 0.5).normalize();
+        let half_angle = 0.6f32;
+        let cos_max = half_angle.cos();
+        let dirs: Vec<Vec3> = (0..SAMPLES)
+            .map(|_| uniform_in_cone(&rng, axis, half_angle))
+            .collect();
+        assert!(dirs.iter().all(|d| d.dot(axis) >= cos_max - 1e-4));
+        // cos(theta) is uniform on [cos_max, 1] for a uniform cap
+        let counts = histogram(dirs.iter().map(|d| d.dot(axis)), cos_max, 1.0, 10);
+        assert!(chi_squared(&counts) < CHI2_9_DOF, "{counts:?}");
+        // and the azimuth around the axis is uniform
+        let (u, v) = axis.any_orthonormal_pair();
+        let counts = histogram(
+            dirs.iter().map(|d| d.dot(v).atan2(d.dot(u))),
+            -std::f32::consts::PI,
+            std::f32::consts::PI,
+            10,
+        );
+        assert!(chi_squared(&counts) < CHI2_9_DOF, "{counts:?}");
+    }
+
+    #[test]
+    fn hemisphere_is_cosine_weighted() {
+        let rng = seeded(4);
+        let normal = vec3(1.0, 2.0, -0.5).normalize();
+        let dirs: Vec<Vec3> = (0..SAMPLES)
+            .map(|_| cosine_hemisphere(&rng, normal))
+            .collect();
+        assert!(dirs.iter().all(|d| d.dot(normal) >= -1e-4));
+        // cos^2(theta) is uniform on [0, 1] for a cosine-weighted hemisphere
+        let counts = histogram(dirs.iter().map(|d| d.dot(normal).powi(2)), 0.0, 1.0, 10);
+        assert!(chi_squared(&counts) < CHI2_9_DOF, "{counts:?}");
+    }
+
+    #[test]
+    fn stratified_covers_every_band() {
+        let rng = seeded(5);
+        let count = 10;
+        for round in 0..100 {
+            let counts = histogram(
+                (0..count).map(|i| stratified_on_sphere(&rng, i, count).z),
+                -1.0,
+                1.0,
+                count,
+            );
+            assert!(counts.iter().all(|&c| c == 1), "round {round}: {counts:?}");
+        }
+        let counts = histogram(
+            (0..SAMPLES).map(|i| stratified_on_sphere(&rng, i % count, count).x),
+            -1.0,
+            1.0,
+            10,
+        );
+        assert!(chi_squared(&counts) < CHI2_9_DOF, "{counts:?}");
+    }
+
+    #[test]
+    fn poisson_disk_keeps_min_distance() {
+        let rng = seeded(6);
+        let mut points = Vec::new();
+        let min_distance = 0.5;
+        poisson_disk(&mut points, 20, min_distance, 100, || {
+            uniform_on_sphere(&rng)
+        });
+        assert_eq!(points.len(), 20);
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[i + 1..] {
+                assert!(a.distance(*b) >= min_distance, "{a} {b}");
+            }
+        }
+        // the accepted points are still spread over the whole sphere
+        let mean = points.iter().copied().sum::<Vec3>() / points.len() as f32;
+        assert!(mean.length() < 0.3, "{mean}");
+    }
+}
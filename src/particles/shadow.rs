@@ -0,0 +1,59 @@
+use macroquad::prelude::*;
+
+/// Soft dark discs on a horizontal floor under every particle, so the
+/// height of a spray can be judged without orbiting the camera. A shadow
+/// fades and spreads as its particle rises, and vanishes at `fade_height`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BlobShadows {
+    pub floor_y: f32,
+    /// Opacity of the shadow of a particle touching the floor.
+    pub opacity: f32,
+    /// Height above the floor at which shadows have faded out.
+    pub fade_height: f32,
+    /// Shadow diameter relative to the particle's size, at the floor.
+    pub scale: f32,
+}
+
+impl BlobShadows {
+    pub fn new(floor_y: f32) -> Self {
+        Self {
+            floor_y,
+            opacity: 0.5,
+            fade_height: 10.0,
+            scale: 1.5,
+        }
+    }
+
+    /// Centre, radius and opacity of the shadow of a particle of `size` at
+    /// `position`, or `None` if it's below the floor or too high to cast one.
+    pub(crate) fn blob(&self, position: Vec3, size: f32) -> Option<(Vec3, f32, f32)> {
+        let height = (position.y - self.floor_y) / self.fade_height;
+        if !(0.0..1.0).contains(&height) {
+            return None;
+        }
+        // lifted a hair so the floor grid doesn't fight it
+        let center = vec3(position.x, self.floor_y + 0.01, position.z);
+        let radius = size * self.scale * 0.5 * (1.0 + height);
+        Some((center, radius, self.opacity * (1.0 - height)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadows_fade_and_spread_with_height() {
+        let shadows = BlobShadows::new(-5.0);
+        let (center, radius, opacity) = shadows.blob(vec3(1.0, -5.0, 2.0), 1.0).unwrap();
+        assert_eq!((center.x, center.z), (1.0, 2.0));
+        assert_eq!((radius, opacity), (0.75, 0.5));
+
+        let (_, high_radius, high_opacity) = shadows.blob(vec3(1.0, 0.0, 2.0), 1.0).unwrap();
+        assert!(high_radius > radius);
+        assert!((high_opacity - 0.25).abs() < 1e-6);
+
+        assert!(shadows.blob(vec3(0.0, 5.0, 0.0), 1.0).is_none());
+        assert!(shadows.blob(vec3(0.0, -6.0, 0.0), 1.0).is_none());
+    }
+}
@@ -0,0 +1,155 @@
+//! Vectorised versions of the integration and force kernels.
+//!
+//! Storage keeps positions and velocities as `Vec3`s, so this is not a
+//! structure-of-arrays kernel: each chunk of `LANES` vectors is copied into
+//! per-component lane arrays, updated with branch-free arithmetic that the
+//! compiler maps to vector instructions, and copied back. Branching on the
+//! force type happens once per chunk instead of once per particle. Energies
+//! and ages are already flat `f32` slices and are stepped the same way as in
+//! the scalar kernel. Tails shorter than a full chunk go through the scalar
+//! kernels.
+
+use crate::particles::{force::Force, passes};
+use macroquad::prelude::*;
+
+pub(crate) const LANES: usize = 8;
+
+type Lane = [f32; LANES];
+
+/// The x, y and z components of `LANES` vectors, one array per component.
+struct Lanes3 {
+    x: Lane,
+    y: Lane,
+    z: Lane,
+}
+
+impl Lanes3 {
+    fn splat(v: Vec3) -> Self {
+        Self {
+            x: [v.x; LANES],
+            y: [v.y; LANES],
+            z: [v.z; LANES],
+        }
+    }
+
+    fn load(vectors: &[Vec3]) -> Self {
+        let mut lanes = Self::splat(Vec3::ZERO);
+        for (i, v) in vectors.iter().enumerate() {
+            lanes.x[i] = v.x;
+            lanes.y[i] = v.y;
+            lanes.z[i] = v.z;
+        }
+        lanes
+    }
+
+    fn store(&self, vectors: &mut [Vec3]) {
+        for (i, v) in vectors.iter_mut().enumerate() {
+            *v = vec3(self.x[i], self.y[i], self.z[i]);
+        }
+    }
+}
+
+/// `a += b * s`, lane by lane.
+fn mul_add(a: &mut Lane, b: &Lane, s: f32) {
+    for i in 0..LANES {
+        a[i] += b[i] * s;
+    }
+}
+
+pub(crate) fn integrate(
+    positions: &mut [Vec3],
+    prev_positions: &mut [Vec3],
+    velocities: &[Vec3],
+    energies: &mut [f32],
+    ages: &mut [f32],
+    delta: f32,
+) {
+    let split = positions.len() - positions.len() % LANES;
+    let (positions, position_tail) = positions.split_at_mut(split);
+    let (prev_positions, prev_tail) = prev_positions.split_at_mut(split);
+    let (velocities, velocity_tail) = velocities.split_at(split);
+    let (energies, energy_tail) = energies.split_at_mut(split);
+    let (ages, age_tail) = ages.split_at_mut(split);
+
+    prev_positions.copy_from_slice(positions);
+    for (positions, velocities) in positions
+        .chunks_exact_mut(LANES)
+        .zip(velocities.chunks_exact(LANES))
+    {
+        let mut p = Lanes3::load(positions);
+        let v = Lanes3::load(velocities);
+        mul_add(&mut p.x, &v.x, delta);
+        mul_add(&mut p.y, &v.y, delta);
+        mul_add(&mut p.z, &v.z, delta);
+        p.store(positions);
+    }
+    for energy in energies {
+        *energy -= delta; // reduction of timespan overtime
+    }
+    for age in ages {
+        *age += delta;
+    }
+
+    passes::integrate_scalar(
+        position_tail,
+        prev_tail,
+        velocity_tail,
+        energy_tail,
+        age_tail,
+        delta,
+    );
+}
+
+pub(crate) fn apply_forces(
+    velocities: &mut [Vec3],
+    positions: &[Vec3],
+    forces: &[Force],
+    delta: f32,
+) {
+    let split = velocities.len() - velocities.len() % LANES;
+    let (velocities, velocity_tail) = velocities.split_at_mut(split);
+    let (positions, position_tail) = positions.split_at(split);
+
+    for (velocities, positions) in velocities
+        .chunks_exact_mut(LANES)
+        .zip(positions.chunks_exact(LANES))
+    {
+        let mut v = Lanes3::load(velocities);
+        let p = Lanes3::load(positions);
+        let mut a = Lanes3::splat(Vec3::ZERO);
+        for force in forces {
+            match *force {
+                Force::Gravity(g) => {
+                    for i in 0..LANES {
+                        a.x[i] += g.x;
+                        a.y[i] += g.y;
+                        a.z[i] += g.z;
+                    }
+                }
+                Force::Drag(k) => {
+                    mul_add(&mut a.x, &v.x, -k);
+                    mul_add(&mut a.y, &v.y, -k);
+                    mul_add(&mut a.z, &v.z, -k);
+                }
+                Force::Attractor { position, strength } => {
+                    for i in 0..LANES {
+                        let dx = position.x - p.x[i];
+                        let dy = position.y - p.y[i];
+                        let dz = position.z - p.z[i];
+                        let dist_sq = dx * dx + dy * dy + dz * dz + 1.0;
+                        let s = strength / (dist_sq * dist_sq.sqrt());
+                        a.x[i] += dx * s;
+                        a.y[i] += dy * s;
+                        a.z[i] += dz * s;
+                    }
+                }
+            }
+        }
+        mul_add(&mut v.x, &a.x, delta);
+        mul_add(&mut v.y, &a.y, delta);
+        mul_add(&mut v.z, &a.z, delta);
+        v.store(velocities);
+    }
+
+    passes::forces_scalar(velocity_tail, position_tail, forces, delta);
+}
@@ -0,0 +1,186 @@
+use crate::particles::{render::RenderContext, storage::ParticleStorage, system::ParticleSystem};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// What the render thread needs to draw a system's particles, captured at
+/// the end of a simulation step.
+pub struct Snapshot {
+    pub(crate) particles: ParticleStorage,
+    /// The system's `dropped_count` after the step.
+    pub(crate) dropped: usize,
+    /// Whether the render thread has yet to take these particles.
+    pub(crate) fresh: bool,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self {
+            particles: ParticleStorage::new(),
+            dropped: 0,
+            fresh: false,
+        }
+    }
+}
+
+/// A change to make to the system before its next step.
+type Command = Box<dyn FnOnce(&mut ParticleSystem) + Send>;
+
+/// Runs a `ParticleSystem` on its own thread at a fixed rate, so heavy
+/// simulation doesn't stall rendering and the UI.
+///
+/// After every step the thread fills a back snapshot and swaps it with the
+/// front one. `draw` takes the front particles into a render-side view of
+/// the system, which has its style, material, trails and shadows, and draws
+/// them as the system itself would. Neither side waits for the other beyond
+/// swapping snapshots: settings reach the thread through `apply`, and stats
+/// come back in the snapshot. The thread stops when this is dropped.
+pub struct SimulationThread {
+    front: Arc<Mutex<Snapshot>>,
+    commands: Sender<Command>,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<ParticleSystem>>,
+    view: RefCell<ParticleSystem>,
+}
+
+impl SimulationThread {
+    /// Move `system` to a new thread that updates it `rate` times per second.
+    /// `rate` must be positive and finite.
+    pub fn spawn(system: ParticleSystem, rate: f32) -> Self {
+        assert!(
+            rate.is_finite() && rate > 0.0,
+            "simulation rate must be positive and finite, got {rate}"
+        );
+        let step = Duration::from_secs_f32(1.0 / rate);
+        let view = RefCell::new(system.render_view());
+        let front = Arc::new(Mutex::new(Snapshot::new()));
+        let running = Arc::new(AtomicBool::new(true));
+        let (commands, received) = mpsc::channel();
+
+        let handle = {
+            let front = Arc::clone(&front);
+            let running = Arc::clone(&running);
+            thread::spawn(move || run(system, &front, &received, &running, step))
+        };
+
+        Self {
+            front,
+            commands,
+            running,
+            handle: Some(handle),
+            view,
+        }
+    }
+
+    /// Change the system, e.g. its settings, without waiting for the step
+    /// in progress. `f` runs on the render-side view right away and on the
+    /// system before its next step.
+    pub fn apply(&mut self, f: impl Fn(&mut ParticleSystem) + Send + 'static) {
+        f(self.view.get_mut());
+        // the thread only hangs up once this is dropped
+        let _ = self.commands.send(Box::new(move |system| f(system)));
+    }
+
+    /// Draw the particles as of the latest finished step.
+    pub fn draw(&self, ctx: &RenderContext) {
+        let mut view = self.view.borrow_mut();
+        view.show_snapshot(&mut self.front.lock().unwrap());
+        view.draw(ctx);
+    }
+
+    /// See `ParticleSystem::draw_legend`.
+    pub fn draw_legend(&self, x: f32, y: f32) {
+        self.view.borrow().draw_legend(x, y);
+    }
+
+    /// The system's `dropped_count` as of the latest drawn step.
+    pub fn dropped_count(&self) -> usize {
+        self.view.borrow().dropped_count()
+    }
+
+    /// Stop the thread and hand the system back.
+    pub fn stop(mut self) -> ParticleSystem {
+        self.join().expect("the simulation thread was running")
+    }
+
+    fn join(&mut self) -> Option<ParticleSystem> {
+        self.running.store(false, Ordering::Relaxed);
+        let handle = self.handle.take()?;
+        Some(handle.join().expect("the simulation thread panicked"))
+    }
+}
+
+impl Drop for SimulationThread {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+fn run(
+    mut system: ParticleSystem,
+    front: &Mutex<Snapshot>,
+    commands: &Receiver<Command>,
+    running: &AtomicBool,
+    step: Duration,
+) -> ParticleSystem {
+    let mut back = Snapshot::new();
+    let mut next = Instant::now();
+
+    while running.load(Ordering::Relaxed) {
+        for command in commands.try_iter() {
+            command(&mut system);
+        }
+        system.update(step.as_secs_f32());
+        system.snapshot_into(&mut back);
+        std::mem::swap(&mut *front.lock().unwrap(), &mut back);
+
+        next += step;
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            // fell behind: drop the missed steps instead of trying to catch up
+            next = now;
+        }
+    }
+    // changes sent after the last step still belong to the system handed back
+    for command in commands.try_iter() {
+        command(&mut system);
+    }
+    system
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::system::ParticleStyle;
+    use crate::particles::utils::Spawn;
+    use macroquad::prelude::*;
+
+    #[test]
+    fn steps_on_its_own_and_publishes_snapshots() {
+        let system = ParticleSystem::new()
+            .sphere(Vec3::ZERO, 1.0, Spawn::Volume)
+            .style(ParticleStyle::Color(WHITE))
+            .seed(1);
+        let mut simulation = SimulationThread::spawn(system, 1000.0);
+        let start = Instant::now();
+        while simulation.front.lock().unwrap().particles.is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::yield_now();
+        }
+
+        // changes reach the thread without stopping it
+        simulation.apply(|system| system.set_max_particles(Some(1)));
+        while simulation.front.lock().unwrap().particles.len() != 1 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::yield_now();
+        }
+
+        let system = simulation.stop();
+        assert!(system.particle_count() > 0);
+    }
+}
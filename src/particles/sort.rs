@@ -0,0 +1,106 @@
+use macroquad::prelude::*;
+
+/// Back-to-front draw order of a system's particles, so alpha-blended
+/// particles composite correctly instead of in storage order.
+///
+/// Squared camera distances are radix sorted, which is linear in the
+/// particle count. The index and key buffers are kept between frames, so a
+/// system of steady size doesn't allocate.
+pub struct DepthOrder {
+    order: Vec<u32>,
+    keys: Vec<u32>,
+    scratch_order: Vec<u32>,
+    scratch_keys: Vec<u32>,
+}
+
+impl DepthOrder {
+    pub fn new() -> Self {
+        Self {
+            order: vec![],
+            keys: vec![],
+            scratch_order: vec![],
+            scratch_keys: vec![],
+        }
+    }
+
+    /// Indices into `positions`, farthest from `eye` first.
+    pub fn sort(&mut self, positions: &[Vec3], eye: Vec3) -> &[u32] {
+        self.order.clear();
+        self.order.extend(0..positions.len() as u32);
+        // non-negative floats order like their bits; inverting them makes
+        // the ascending sort below put the farthest particles first
+        self.keys.clear();
+        self.keys
+            .extend(positions.iter().map(|p| !p.distance_squared(eye).to_bits()));
+        self.scratch_order.resize(positions.len(), 0);
+        self.scratch_keys.resize(positions.len(), 0);
+
+        for shift in [0, 8, 16, 24] {
+            let mut counts = [0usize; 256];
+            for &key in &self.keys {
+                counts[(key >> shift) as usize & 0xff] += 1;
+            }
+            // every key has the same digit: this pass wouldn't move anything
+            if counts.contains(&self.keys.len()) {
+                continue;
+            }
+
+            let mut offset = 0;
+            for count in &mut counts {
+                let start = offset;
+                offset += *count;
+                *count = start;
+            }
+            for (&key, &index) in self.keys.iter().zip(&self.order) {
+                let slot = &mut counts[(key >> shift) as usize & 0xff];
+                self.scratch_keys[*slot] = key;
+                self.scratch_order[*slot] = index;
+                *slot += 1;
+            }
+            std::mem::swap(&mut self.keys, &mut self.scratch_keys);
+            std::mem::swap(&mut self.order, &mut self.scratch_order);
+        }
+
+        &self.order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_back_to_front() {
+        let positions: Vec<Vec3> = (0..5000)
+            .map(|i| {
+                let f = i as f32 * 0.37;
+                vec3(
+                    f.sin() * 20.0,
+                    (f * 1.3).cos() * 5.0,
+                    (f * 0.7).sin() * 40.0,
+                )
+            })
+            .collect();
+        let eye = vec3(3.0, 10.0, 50.0);
+
+        let mut expected: Vec<u32> = (0..positions.len() as u32).collect();
+        expected.sort_by(|&a, &b| {
+            let da = positions[a as usize].distance_squared(eye);
+            let db = positions[b as usize].distance_squared(eye);
+            db.total_cmp(&da)
+        });
+
+        let mut order = DepthOrder::new();
+        let sorted = order.sort(&positions, eye);
+        let distances = |indices: &[u32]| -> Vec<f32> {
+            indices
+                .iter()
+                .map(|&i| positions[i as usize].distance_squared(eye))
+                .collect()
+        };
+        assert_eq!(distances(sorted), distances(&expected));
+
+        // reusing the buffers for a smaller system
+        assert_eq!(order.sort(&positions[..2], Vec3::ZERO).len(), 2);
+    }
+}
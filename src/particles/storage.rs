@@ -0,0 +1,308 @@
+use crate::particles::particle::Particle;
+use macroquad::prelude::*;
+
+/// Structure-of-arrays particle storage: each attribute lives in its own
+/// contiguous slice, indexed by particle. Hot loops should read the
+/// per-attribute slices; `push`, `get` and `iter` move whole `Particle`s in
+/// and out for emitters and code that needs every attribute of a particle.
+///
+/// Removal swaps the last particle into the freed slot, so indices are only
+/// stable until the next removal and particle order is not preserved. The
+/// arrays never shrink: slots of dead particles are reused by later spawns,
+/// so a system at steady state doesn't allocate.
+///
+/// Trails keep a ring buffer of `trail_length` past positions per particle,
+/// which moves and dies with its particle.
+pub struct ParticleStorage {
+    positions: Vec<Vec3>,
+    prev_positions: Vec<Vec3>,
+    velocities: Vec<Vec3>,
+    energies: Vec<f32>,
+    sizes: Vec<f32>,
+    rotations: Vec<f32>,
+    variations: Vec<f32>,
+    ages: Vec<f32>,
+    trail_length: usize,
+    /// `trail_length` slots per particle.
+    trail_points: Vec<Vec3>,
+    /// Slot of each particle's newest trail point.
+    trail_heads: Vec<u32>,
+    /// Number of trail points each particle has recorded so far.
+    trail_counts: Vec<u32>,
+}
+
+/// Mutable views of every attribute at once, for passes that touch several.
+pub struct ParticleAttributesMut<'a> {
+    pub positions: &'a mut [Vec3],
+    pub prev_positions: &'a mut [Vec3],
+    pub velocities: &'a mut [Vec3],
+    pub energies: &'a mut [f32],
+    pub ages: &'a mut [f32],
+}
+
+impl ParticleStorage {
+    pub fn new() -> Self {
+        Self {
+            positions: vec![],
+            prev_positions: vec![],
+            velocities: vec![],
+            energies: vec![],
+            sizes: vec![],
+            rotations: vec![],
+            variations: vec![],
+            ages: vec![],
+            trail_length: 0,
+            trail_points: vec![],
+            trail_heads: vec![],
+            trail_counts: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn push(&mut self, particle: Particle) {
+        self.positions.push(particle.position);
+        self.prev_positions.push(particle.prev_position);
+        self.velocities.push(particle.velocity);
+        self.energies.push(particle.energy);
+        self.sizes.push(particle.size);
+        self.rotations.push(particle.rotation);
+        self.variations.push(particle.variation);
+        self.ages.push(particle.age);
+        // the trail starts at the first `record_trails`, once the spawn
+        // position is final
+        self.trail_points
+            .extend(std::iter::repeat_n(Vec3::ZERO, self.trail_length));
+        self.trail_heads.push(0);
+        self.trail_counts.push(0);
+    }
+
+    /// Copy of the particle at `index`.
+    pub fn get(&self, index: usize) -> Particle {
+        Particle {
+            position: self.positions[index],
+            prev_position: self.prev_positions[index],
+            velocity: self.velocities[index],
+            energy: self.energies[index],
+            size: self.sizes[index],
+            rotation: self.rotations[index],
+            variation: self.variations[index],
+            age: self.ages[index],
+        }
+    }
+
+    /// Copies of all particles, in storage order.
+    pub fn iter(&self) -> impl Iterator<Item = Particle> + '_ {
+        (0..self.len()).map(|index| self.get(index))
+    }
+
+    /// Remove the particle at `index`, moving the last particle into its slot.
+    pub fn swap_remove(&mut self, index: usize) {
+        let last = self.len() - 1;
+        let length = self.trail_length;
+        if index != last {
+            self.trail_points
+                .copy_within(last * length..(last + 1) * length, index * length);
+        }
+        self.trail_points.truncate(last * length);
+        self.trail_heads.swap_remove(index);
+        self.trail_counts.swap_remove(index);
+        self.positions.swap_remove(index);
+        self.prev_positions.swap_remove(index);
+        self.velocities.swap_remove(index);
+        self.energies.swap_remove(index);
+        self.sizes.swap_remove(index);
+        self.rotations.swap_remove(index);
+        self.variations.swap_remove(index);
+        self.ages.swap_remove(index);
+    }
+
+    /// Keep the particles for which `keep` returns true, compacting by
+    /// swap-remove instead of shifting the arrays.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Self, usize) -> bool) {
+        // walk backwards so the particle swapped into `index` was already visited
+        for index in (0..self.len()).rev() {
+            if !keep(self, index) {
+                self.swap_remove(index);
+            }
+        }
+    }
+
+    /// Make this a copy of `other`, reusing the arrays' capacity.
+    pub fn copy_from(&mut self, other: &Self) {
+        self.positions.clone_from(&other.positions);
+        self.prev_positions.clone_from(&other.prev_positions);
+        self.velocities.clone_from(&other.velocities);
+        self.energies.clone_from(&other.energies);
+        self.sizes.clone_from(&other.sizes);
+        self.rotations.clone_from(&other.rotations);
+        self.variations.clone_from(&other.variations);
+        self.ages.clone_from(&other.ages);
+        self.trail_length = other.trail_length;
+        self.trail_points.clone_from(&other.trail_points);
+        self.trail_heads.clone_from(&other.trail_heads);
+        self.trail_counts.clone_from(&other.trail_counts);
+    }
+
+    /// Keep the last `length` positions of every particle, dropping the
+    /// trails recorded so far. 0 turns trails off.
+    pub fn set_trail_length(&mut self, length: usize) {
+        self.trail_length = length;
+        self.trail_points.clear();
+        self.trail_points.resize(self.len() * length, Vec3::ZERO);
+        self.trail_heads.fill(0);
+        self.trail_counts.fill(0);
+    }
+
+    /// Add each particle's position to its trail, if it has moved at least
+    /// `spacing` from the newest point; 0 records every call.
+    pub fn record_trails(&mut self, spacing: f32) {
+        let length = self.trail_length;
+        if length == 0 {
+            return;
+        }
+        let chunks = self.trail_points.chunks_exact_mut(length);
+        for (((points, head), count), &position) in chunks
+            .zip(&mut self.trail_heads)
+            .zip(&mut self.trail_counts)
+            .zip(&self.positions)
+        {
+            if *count > 0 {
+                if points[*head as usize].distance_squared(position) < spacing * spacing {
+                    continue;
+                }
+                *head = (*head + 1) % length as u32;
+            }
+            points[*head as usize] = position;
+            *count = (*count + 1).min(length as u32);
+        }
+    }
+
+    /// Trail of the particle at `index`, newest point first.
+    pub fn trail(&self, index: usize) -> impl ExactSizeIterator<Item = Vec3> + '_ {
+        let length = self.trail_length;
+        let points = &self.trail_points[index * length..(index + 1) * length];
+        let head = self.trail_heads[index] as usize;
+        (0..self.trail_counts[index] as usize)
+            .map(move |age| points[(head + length - age) % length])
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    pub fn velocities(&self) -> &[Vec3] {
+        &self.velocities
+    }
+
+    pub fn sizes(&self) -> &[f32] {
+        &self.sizes
+    }
+
+    pub fn energies(&self) -> &[f32] {
+        &self.energies
+    }
+
+    pub fn ages(&self) -> &[f32] {
+        &self.ages
+    }
+
+    pub fn attributes_mut(&mut self) -> ParticleAttributesMut<'_> {
+        ParticleAttributesMut {
+            positions: &mut self.positions,
+            prev_positions: &mut self.prev_positions,
+            velocities: &mut self.velocities,
+            energies: &mut self.energies,
+            ages: &mut self.ages,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn particle(i: usize) -> Particle {
+        let f = i as f32;
+        let mut particle = Particle::at(vec3(f, 0.0, 0.0), vec3(0.0, 0.0, f));
+        particle.prev_position = vec3(0.0, f, 0.0);
+        particle.energy = f;
+        particle.size = f * 2.0;
+        particle.rotation = f * 4.0;
+        particle.variation = f * 5.0;
+        particle.age = f * 3.0;
+        particle
+    }
+
+    fn assert_aligned(storage: &ParticleStorage) {
+        for p in storage.iter() {
+            let f = p.position.x;
+            assert_eq!(p.prev_position.y, f);
+            assert_eq!(p.velocity.z, f);
+            assert_eq!(p.energy, f);
+            assert_eq!(p.size, f * 2.0);
+            assert_eq!(p.rotation, f * 4.0);
+            assert_eq!(p.variation, f * 5.0);
+            assert_eq!(p.age, f * 3.0);
+        }
+    }
+
+    #[test]
+    fn swap_remove_moves_last_into_slot() {
+        let mut storage = ParticleStorage::new();
+        (0..5).for_each(|i| storage.push(particle(i)));
+        storage.swap_remove(1);
+        assert_eq!(storage.len(), 4);
+        assert_eq!(storage.energies(), &[0.0, 4.0, 2.0, 3.0]);
+        assert_aligned(&storage);
+    }
+
+    #[test]
+    fn retain_keeps_attributes_together() {
+        let mut storage = ParticleStorage::new();
+        (0..10).for_each(|i| storage.push(particle(i)));
+        storage.retain(|s, i| !(s.energies()[i] as usize).is_multiple_of(3));
+        let mut kept: Vec<f32> = storage.energies().to_vec();
+        kept.sort_by(f32::total_cmp);
+        assert_eq!(kept, vec![1.0, 2.0, 4.0, 5.0, 7.0, 8.0]);
+        assert_aligned(&storage);
+    }
+
+    #[test]
+    fn trails_wrap_and_follow_their_particle() {
+        let mut storage = ParticleStorage::new();
+        storage.set_trail_length(3);
+        (0..3).for_each(|i| storage.push(particle(i)));
+        for step in 1..=4 {
+            for position in storage.attributes_mut().positions {
+                position.y = step as f32;
+            }
+            storage.record_trails(0.0);
+        }
+        let trail: Vec<Vec3> = storage.trail(2).collect();
+        assert_eq!(
+            trail,
+            vec![
+                vec3(2.0, 4.0, 0.0),
+                vec3(2.0, 3.0, 0.0),
+                vec3(2.0, 2.0, 0.0)
+            ]
+        );
+
+        // particle 2's history moves into the freed slot
+        storage.swap_remove(0);
+        assert!(storage.trail(0).eq(trail.iter().copied()));
+        assert_eq!(storage.trail(1).next(), Some(vec3(1.0, 4.0, 0.0)));
+
+        // points closer than the spacing aren't recorded
+        storage.attributes_mut().positions[0].y = 4.5;
+        storage.record_trails(1.0);
+        assert_eq!(storage.trail(0).next(), Some(vec3(2.0, 4.0, 0.0)));
+    }
+}
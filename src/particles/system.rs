@@ -1,148 +1,1118 @@
 use crate::particles::{
-    emitter::Emitter,
+    batch::{LineBatch, MeshBatch, QuadBatch},
+    colormap::{self, AttributeValues, ColorAttribute, ColorRange, Colormap},
+    emitter::{Emitter, NamedEmitter},
+    flipbook::Flipbook,
+    force::{Collider, Force},
+    material::{BlendMode, MaterialSettings},
+    mesh::ParticleMesh,
     particle::Particle,
-    utils::{Direction, Spawn},
+    passes::{self, Kernel},
+    path::MotionPath,
+    render::RenderContext,
+    shadow::BlobShadows,
+    simulation::Snapshot,
+    sort::DepthOrder,
+    storage::ParticleStorage,
+    trail::{self, Trail, TrailStyle},
+    utils::{Direction, ParticleQuad, Spawn},
 };
 use macroquad::prelude::*;
+use macroquad::rand::RandGenerator;
+use std::cell::RefCell;
+use std::f32::consts::TAU;
+use std::sync::Arc;
 
+#[derive(Clone)]
 pub enum ParticleStyle {
+    /// Camera-facing textured quads tinted by `color`, fading out as they die.
+    /// With a `flipbook`, the texture is a sprite sheet and each particle
+    /// shows its current frame.
     Texture {
         texture: Texture2D,
         color: Color,
+        flipbook: Option<Flipbook>,
     },
     /// Single color (keeps previous behavior). Particles will lerp to RED as they die.
     Color(Color),
     /// Gradient from start color to end color over particle lifetime.
     ColorGradient(Color, Color),
+    /// Streaks from each particle back to where its velocity had it
+    /// `stretch` seconds earlier, fading out towards the tail and as the
+    /// particle dies. Drawn as lines when `width` is 0, otherwise as
+    /// camera-facing quads that wide. For rain and sparks.
+    Streak {
+        color: Color,
+        stretch: f32,
+        width: f32,
+    },
+    /// A copy of `mesh` per particle, scaled by its size, tinted by `color`
+    /// and fading out as it dies. The particle's rotation turns the mesh
+    /// about an axis picked from its variation, so debris lands at
+    /// different angles.
+    Mesh {
+        mesh: Arc<ParticleMesh>,
+        color: Color,
+    },
+    /// Crosses coloured by `attribute` through `colormap`, for analysing a
+    /// simulation rather than rendering an effect. See `draw_legend`.
+    Colormap {
+        attribute: ColorAttribute,
+        colormap: Colormap,
+        range: ColorRange,
+    },
+}
+
+/// What a system does when spawning would take it past its particle limit.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OverflowPolicy {
+    /// Don't spawn particles that don't fit.
+    Refuse,
+    /// Spawn, then kill the particles that have lived longest.
+    KillOldest,
+    /// Spawn, then kill the particles with the least energy left.
+    KillLeastEnergy,
 }
 
 pub struct ParticleSystem {
     style: Option<ParticleStyle>,
-    // blend_mode: BlendMode,
-    particles: Vec<Particle>,
+    /// Blending and depth settings the particles are drawn with.
+    material: MaterialSettings,
+    /// Draw particles back to front when the blend mode depends on order.
+    depth_sort: bool,
+    trail: Option<Trail>,
+    shadows: Option<BlobShadows>,
+    particles: ParticleStorage,
     bounding_box: Option<(Vec3, Vec3)>,
-    emitter: Emitter,
+    /// Emitters feeding this system's particle pool, updated in order.
+    emitters: Vec<NamedEmitter>,
+    /// Emitters ever added through the shape builders, numbering their
+    /// names so they stay unique after removals.
+    shapes_added: usize,
+    /// Spawn rate given to emitters added through the shape builders.
     spawn_per_update: usize,
+    /// Size of newly spawned particles.
+    particle_size: f32,
+    /// Forces applied to every particle each update; gravity by default.
+    forces: Vec<Force>,
+    colliders: Vec<Collider>,
+    kernel: Kernel,
+    /// Each system owns its RNG so runs are reproducible from `seed` and
+    /// systems don't disturb each other's sequences.
+    rng: RandGenerator,
+    seed: u64,
+    max_particles: Option<usize>,
+    /// Tighter limit handed down by a `ParticleManager` enforcing a global budget.
+    budget_limit: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    /// Particles refused or killed to respect the limit during the last update.
+    dropped: usize,
+    /// Reused buffer for the overflow policies' sort keys.
+    scratch_keys: Vec<f32>,
+    /// Reused buffer for emitters' spawn offsets.
+    scratch_points: Vec<Vec3>,
+    /// Line, quad and particle mesh batches rebuilt by every `draw`.
+    batch: RefCell<LineBatch>,
+    quads: RefCell<QuadBatch>,
+    meshes: RefCell<MeshBatch>,
+    depth_order: RefCell<DepthOrder>,
+    /// Values of a `Colormap` style's attribute, refreshed by every `draw`.
+    attribute_values: RefCell<AttributeValues>,
 }
 
 impl ParticleSystem {
     pub fn new() -> Self {
+        let seed = rand::rand() as u64;
+        let rng = RandGenerator::new();
+        rng.srand(seed);
         Self {
-            emitter: Emitter::Point {
-                position: vec3(0.0, 0.0, 0.0),
-                direction: Direction::Fixed(vec3(0.0, 0.0, 0.0)),
-                spread: 0.3,
-            },
+            emitters: vec![],
+            shapes_added: 0,
             bounding_box: None,
-            particles: vec![],
+            particles: ParticleStorage::new(),
             style: None,
+            material: MaterialSettings::default(),
+            depth_sort: false,
+            trail: None,
+            shadows: None,
             spawn_per_update: 2,
+            particle_size: 0.1,
+            forces: vec![Force::Gravity(vec3(0.0, -9.8, 0.0))],
+            colliders: vec![],
+            kernel: Kernel::Simd,
+            rng,
+            seed,
+            max_particles: None,
+            budget_limit: None,
+            overflow_policy: OverflowPolicy::Refuse,
+            dropped: 0,
+            scratch_keys: vec![],
+            scratch_points: vec![],
+            batch: RefCell::new(LineBatch::new()),
+            quads: RefCell::new(QuadBatch::new()),
+            meshes: RefCell::new(MeshBatch::new()),
+            depth_order: RefCell::new(DepthOrder::new()),
+            attribute_values: RefCell::new(AttributeValues::new()),
+        }
+    }
+
+    /// Reseed the system's RNG. The same seed and the same sequence of
+    /// `update()` deltas reproduce the same particles bit for bit.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng.srand(seed);
+        self
+    }
+
+    /// Never hold more than `max` particles; see `overflow_policy`.
+    pub fn max_particles(mut self, max: usize) -> Self {
+        self.max_particles = Some(max);
+        self
+    }
+
+    pub fn set_max_particles(&mut self, max: Option<usize>) {
+        self.max_particles = max;
+    }
+
+    pub fn max_particle_count(&self) -> Option<usize> {
+        self.max_particles
+    }
+
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    pub(crate) fn set_budget_limit(&mut self, limit: Option<usize>) {
+        self.budget_limit = limit;
+    }
+
+    /// Number of particles refused or killed during the last update because
+    /// the system was at its limit.
+    pub fn dropped_count(&self) -> usize {
+        self.dropped
+    }
+
+    /// The tighter of `max_particles` and the manager's budget limit.
+    fn particle_limit(&self) -> Option<usize> {
+        match (self.max_particles, self.budget_limit) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
         }
     }
 
-    /// Configure how many particles to spawn per update() call.
+    /// Replace the forces acting on the particles (gravity by default).
+    pub fn forces(mut self, forces: Vec<Force>) -> Self {
+        self.forces = forces;
+        self
+    }
+
+    pub fn set_forces(&mut self, forces: Vec<Force>) {
+        self.forces = forces;
+    }
+
+    pub fn active_forces(&self) -> &[Force] {
+        &self.forces
+    }
+
+    pub fn collider(mut self, collider: Collider) -> Self {
+        self.colliders.push(collider);
+        self
+    }
+
+    /// Choose the scalar or vectorised integration and force kernels. Both
+    /// give the same results up to floating point rounding.
+    pub fn set_kernel(&mut self, kernel: Kernel) {
+        self.kernel = kernel;
+    }
+
+    /// How particles blend with what's behind them; alpha by default.
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.material.blend_mode = blend_mode;
+        self
+    }
+
+    /// Whether particles write depth, hiding what's drawn after them behind
+    /// them. On by default; usually turned off for translucent effects.
+    pub fn depth_write(mut self, enabled: bool) -> Self {
+        self.material.depth_write = enabled;
+        self
+    }
+
+    /// Whether particles are hidden behind closer geometry. On by default.
+    pub fn depth_test(mut self, enabled: bool) -> Self {
+        self.material.depth_test = enabled;
+        self
+    }
+
+    /// Sort particles back to front every frame before drawing them with an
+    /// alpha or premultiplied blend mode, which otherwise blend in storage
+    /// order and pop as particles die. Off by default.
+    pub fn depth_sort(mut self, enabled: bool) -> Self {
+        self.depth_sort = enabled;
+        self
+    }
+
+    pub fn set_depth_sort(&mut self, enabled: bool) {
+        self.depth_sort = enabled;
+    }
+
+    /// Draw a trail behind every particle, starting from the next update.
+    /// Trails travel with the particles in a `SimulationThread`'s snapshots,
+    /// so they're drawn there too.
+    pub fn trails(mut self, trail: Trail) -> Self {
+        self.particles.set_trail_length(trail.length);
+        self.trail = Some(trail);
+        self
+    }
+
+    /// Draw a blob shadow on the floor under every particle.
+    pub fn shadows(mut self, shadows: BlobShadows) -> Self {
+        self.shadows = Some(shadows);
+        self
+    }
+
+    /// Configure how many particles each emitter spawns per update() call.
+    /// Applies to the emitters added so far and to those added later
+    /// through `point`, `cube` and `sphere`.
     pub fn spawn_rate(mut self, per_update: usize) -> Self {
         self.spawn_per_update = per_update;
+        for emitter in &mut self.emitters {
+            emitter.spawn_per_update = per_update;
+        }
         self
     }
 
-    pub fn point(mut self, position: Vec3, direction: Direction, spread: f32) -> Self {
-        self.emitter = Emitter::Point {
-            position,
-            direction,
-            spread,
-        };
+    /// Size of particles spawned from now on: the edge of their quad, or the
+    /// length of their cross's arms.
+    pub fn particle_size(mut self, size: f32) -> Self {
+        self.particle_size = size;
         self
     }
 
-    pub fn cube(mut self, position: Vec3, size: f32, spawn_type: Spawn) -> Self {
-        self.emitter = Emitter::Cube {
-            position,
-            size,
-            spawn_type,
-        };
+    /// Add a point emitter named `"point <n>"`.
+    pub fn point(self, position: Vec3, direction: Direction, spread: f32) -> Self {
+        self.shape(
+            "point",
+            Emitter::Point {
+                position,
+                direction,
+                spread,
+            },
+        )
+    }
+
+    /// Add a cube emitter named `"cube <n>"`.
+    pub fn cube(self, position: Vec3, size: f32, spawn_type: Spawn) -> Self {
+        self.shape(
+            "cube",
+            Emitter::Cube {
+                position,
+                size,
+                spawn_type,
+            },
+        )
+    }
+
+    /// Add a sphere emitter named `"sphere <n>"`.
+    pub fn sphere(self, position: Vec3, size: f32, spawn_type: Spawn) -> Self {
+        self.shape(
+            "sphere",
+            Emitter::Sphere {
+                position,
+                size,
+                spawn_type,
+            },
+        )
+    }
+
+    fn shape(mut self, kind: &str, emitter: Emitter) -> Self {
+        let name = format!("{kind} {}", self.shapes_added);
+        self.shapes_added += 1;
+        let rate = self.spawn_per_update;
+        self.emitter(NamedEmitter::new(name, emitter).spawn_rate(rate))
+    }
+
+    pub fn emitter(mut self, emitter: NamedEmitter) -> Self {
+        self.add_emitter(emitter);
         self
     }
 
-    pub fn sphere(mut self, position: Vec3, size: f32, spawn_type: Spawn) -> Self {
-        self.emitter = Emitter::Sphere {
-            position,
-            size,
-            spawn_type,
-        };
+    /// Drive the most recently added emitter from a motion path.
+    pub fn path(mut self, path: MotionPath) -> Self {
+        if let Some(emitter) = self.emitters.pop() {
+            self.emitters.push(emitter.path(path));
+        }
         self
     }
 
+    pub fn add_emitter(&mut self, emitter: NamedEmitter) {
+        self.emitters.push(emitter);
+    }
+
+    /// Remove the first emitter called `name`. Its particles stay alive.
+    pub fn remove_emitter(&mut self, name: &str) -> Option<NamedEmitter> {
+        let index = self.emitters.iter().position(|e| e.name == name)?;
+        Some(self.emitters.remove(index))
+    }
+
+    pub fn emitter_mut(&mut self, name: &str) -> Option<&mut NamedEmitter> {
+        self.emitters.iter_mut().find(|e| e.name == name)
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn emitters(&self) -> &[NamedEmitter] {
+        &self.emitters
+    }
+
+    pub fn emitters_mut(&mut self) -> &mut [NamedEmitter] {
+        &mut self.emitters
+    }
+
     pub fn style(mut self, style: ParticleStyle) -> Self {
         self.style = Some(style);
         self
     }
 
+    pub fn set_style(&mut self, style: ParticleStyle) {
+        self.style = Some(style);
+    }
+
     pub fn bounding_box(mut self, bounding_box: (Vec3, Vec3)) -> Self {
         self.bounding_box = Some(bounding_box);
         self
     }
 
-    pub fn draw(&self) {
+    pub fn draw(&self, ctx: &RenderContext) {
+        for emitter in &self.emitters {
+            emitter.draw_path();
+        }
+
+        if self.particles.is_empty() {
+            return;
+        }
+
+        if let Some(shadows) = &self.shadows {
+            self.draw_shadows(shadows, ctx);
+        }
+
         if let Some(style) = &self.style {
+            self.compute_attribute_values(style);
             match style {
-                ParticleStyle::Color(color) => self.draw_color_particles(color, &RED),
-                ParticleStyle::ColorGradient(start, end) => self.draw_color_particles(start, end),
-                ParticleStyle::Texture { texture, color } => {
-                    self.draw_texture_particles(texture, color)
+                ParticleStyle::Color(color) => self.draw_color_particles(color, &RED, ctx),
+                ParticleStyle::ColorGradient(start, end) => {
+                    self.draw_color_particles(start, end, ctx)
+                }
+                ParticleStyle::Texture {
+                    texture,
+                    color,
+                    flipbook,
+                } => self.draw_texture_particles(texture, color, flipbook.as_ref(), ctx),
+                ParticleStyle::Streak { stretch, width, .. } => {
+                    self.draw_streak_particles(style, *stretch, *width, ctx)
+                }
+                ParticleStyle::Mesh { mesh, .. } => self.draw_mesh_particles(style, mesh, ctx),
+                ParticleStyle::Colormap { .. } => self.draw_colormap_particles(style, ctx),
+            }
+            if let Some(trail) = &self.trail {
+                self.draw_trails(trail, style, ctx);
+            }
+        }
+    }
+
+    fn draw_shadows(&self, shadows: &BlobShadows, ctx: &RenderContext) {
+        let mut meshes = self.meshes.borrow_mut();
+        meshes.clear();
+        let sizes = self.particles.sizes();
+        for (&position, &size) in self.particles.positions().iter().zip(sizes) {
+            if let Some((center, radius, opacity)) = shadows.blob(position, size) {
+                meshes.soft_disc(center, radius, Color::new(0.0, 0.0, 0.0, opacity));
+            }
+        }
+        // shadows lie on the floor: hidden behind things, but hiding nothing
+        meshes.apply_fog(ctx, BlendMode::Alpha);
+        meshes.draw(MaterialSettings {
+            blend_mode: BlendMode::Alpha,
+            depth_write: false,
+            depth_test: true,
+        });
+    }
+
+    fn draw_trails(&self, trail: &Trail, style: &ParticleStyle, ctx: &RenderContext) {
+        match trail.style {
+            TrailStyle::Line => {
+                let mut batch = self.batch.borrow_mut();
+                batch.clear();
+                for (i, particle) in self.particles.iter().enumerate() {
+                    let color = self.particle_color(style, i, &particle);
+                    trail::line(
+                        &mut batch,
+                        particle.position,
+                        self.particles.trail(i),
+                        color,
+                    );
+                }
+                batch.apply_fog(ctx, self.material.blend_mode);
+                batch.draw(self.material);
+            }
+            TrailStyle::Ribbon => {
+                let mut quads = self.quads.borrow_mut();
+                quads.clear();
+                for (i, particle) in self.particles.iter().enumerate() {
+                    let color = self.particle_color(style, i, &particle);
+                    trail::ribbon(
+                        &mut quads,
+                        particle.position,
+                        self.particles.trail(i),
+                        trail.width,
+                        color,
+                        ctx,
+                    );
                 }
+                quads.apply_fog(ctx, self.material.blend_mode);
+                quads.draw(None, self.material);
             }
         }
     }
 
-    fn draw_color_particles(&self, start_color: &Color, end_color: &Color) {
+    /// Call `f` with the index of every particle and the particle, back to
+    /// front if depth sorting applies.
+    fn for_each_in_draw_order(&self, ctx: &RenderContext, mut f: impl FnMut(usize, Particle)) {
+        if self.depth_sort && self.material.blend_mode.is_order_dependent() {
+            let mut order = self.depth_order.borrow_mut();
+            for &index in order.sort(self.particles.positions(), ctx.camera_position) {
+                f(index as usize, self.particles.get(index as usize));
+            }
+        } else {
+            for (index, particle) in self.particles.iter().enumerate() {
+                f(index, particle);
+            }
+        }
+    }
+
+    /// Colour of `particle`, at `index` in storage, in `style`. Textured,
+    /// streak and mesh particles fade out as they die.
+    fn particle_color(&self, style: &ParticleStyle, index: usize, particle: &Particle) -> Color {
+        match style {
+            ParticleStyle::Texture { color, .. }
+            | ParticleStyle::Streak { color, .. }
+            | ParticleStyle::Mesh { color, .. } => {
+                lifetime_color(color, &Color { a: 0.0, ..*color }, particle.energy)
+            }
+            ParticleStyle::Color(color) => lifetime_color(color, &RED, particle.energy),
+            ParticleStyle::ColorGradient(start, end) => lifetime_color(start, end, particle.energy),
+            ParticleStyle::Colormap { colormap, .. } => {
+                self.attribute_values.borrow().color(*colormap, index)
+            }
+        }
+    }
+
+    /// Refresh the attribute values if `style` is a `Colormap`.
+    fn compute_attribute_values(&self, style: &ParticleStyle) {
+        if let ParticleStyle::Colormap {
+            attribute, range, ..
+        } = style
+        {
+            self.attribute_values
+                .borrow_mut()
+                .compute(*attribute, *range, &self.particles);
+        }
+    }
+
+    fn draw_colormap_particles(&self, style: &ParticleStyle, ctx: &RenderContext) {
+        let mut batch = self.batch.borrow_mut();
+        batch.clear();
+        self.for_each_in_draw_order(ctx, |index, particle| {
+            let color = self.particle_color(style, index, &particle);
+            batch.cross(particle.position, particle.size, color);
+        });
+        batch.apply_fog(ctx, self.material.blend_mode);
+        batch.draw(self.material);
+    }
+
+    /// Draw a colour bar for a `Colormap` style at (`x`, `y`) on screen,
+    /// labelled with the attribute and the range of the last `draw`. Does
+    /// nothing for other styles. Call after `set_default_camera`.
+    pub fn draw_legend(&self, x: f32, y: f32) {
+        if let Some(ParticleStyle::Colormap {
+            attribute,
+            colormap,
+            ..
+        }) = &self.style
+        {
+            let range = self.attribute_values.borrow().range;
+            colormap::draw_colorbar(*colormap, attribute.name(), range, x, y);
+        }
+    }
+
+    fn draw_color_particles(&self, start_color: &Color, end_color: &Color, ctx: &RenderContext) {
         // draw a small 3D cross for each particle so depth is visible
-        for particle in &self.particles {
-            let p = particle.position;
-            let s = particle.size * 0.5;
-            // energy ranges from 1.0 -> 0.0; progress = 1 - energy
-            let t = (1.0 - particle.energy).clamp(0.0, 1.0);
-            let r = start_color.r + (end_color.r - start_color.r) * t;
-            let g = start_color.g + (end_color.g - start_color.g) * t;
-            let b = start_color.b + (end_color.b - start_color.b) * t;
-            let a = start_color.a + (end_color.a - start_color.a) * t;
-            let color = Color::new(r, g, b, a);
-            // X axis line
-            draw_line_3d(p - vec3(s, 0.0, 0.0), p + vec3(s, 0.0, 0.0), color);
-            // Y axis line
-            draw_line_3d(p - vec3(0.0, s, 0.0), p + vec3(0.0, s, 0.0), color);
-            // Z axis line
-            draw_line_3d(p - vec3(0.0, 0.0, s), p + vec3(0.0, 0.0, s), color);
-        }
-    }
-
-    fn draw_texture_particles(&self, texture: &Texture2D, color: &Color) {
-        for particle in &self.particles {
-            draw_texture_ex(
-                texture,
-                particle.position.x,
-                particle.position.y,
-                *color,
-                DrawTextureParams {
-                    dest_size: Some(vec2(particle.size, particle.size)),
-                    ..Default::default()
-                },
+        let mut batch = self.batch.borrow_mut();
+        batch.clear();
+        self.for_each_in_draw_order(ctx, |_, particle| {
+            let color = lifetime_color(start_color, end_color, particle.energy);
+            batch.cross(particle.position, particle.size, color);
+        });
+        batch.apply_fog(ctx, self.material.blend_mode);
+        batch.draw(self.material);
+    }
+
+    /// Copy the particles and the last update's stats into `snapshot`.
+    pub(crate) fn snapshot_into(&self, snapshot: &mut Snapshot) {
+        snapshot.particles.copy_from(&self.particles);
+        snapshot.dropped = self.dropped;
+        snapshot.fresh = true;
+    }
+
+    /// A particle-less copy of the system's emitters and drawing settings,
+    /// for drawing its snapshots on the render thread.
+    pub(crate) fn render_view(&self) -> Self {
+        let mut view = Self::new();
+        view.style = self.style.clone();
+        view.material = self.material;
+        view.depth_sort = self.depth_sort;
+        view.trail = self.trail;
+        view.shadows = self.shadows;
+        view.emitters = self.emitters.clone();
+        view
+    }
+
+    /// Take the particles and stats of `snapshot` if it's newer than the
+    /// ones held, handing the old particles' arrays back for reuse.
+    pub(crate) fn show_snapshot(&mut self, snapshot: &mut Snapshot) {
+        if snapshot.fresh {
+            std::mem::swap(&mut self.particles, &mut snapshot.particles);
+            self.dropped = snapshot.dropped;
+            snapshot.fresh = false;
+        }
+    }
+
+    fn draw_texture_particles(
+        &self,
+        texture: &Texture2D,
+        color: &Color,
+        flipbook: Option<&Flipbook>,
+        ctx: &RenderContext,
+    ) {
+        // fade out over the particle's lifetime
+        let end_color = Color { a: 0.0, ..*color };
+        let whole = Rect::new(0.0, 0.0, 1.0, 1.0);
+        let mut quads = self.quads.borrow_mut();
+        quads.clear();
+        self.for_each_in_draw_order(ctx, |_, particle| {
+            let quad =
+                ParticleQuad::billboard(particle.position, particle.size, particle.rotation, ctx);
+            let uv = match flipbook {
+                Some(flipbook) => {
+                    flipbook.uv(flipbook.frame(particle.age, particle.energy, particle.variation))
+                }
+                None => whole,
+            };
+            let tint = lifetime_color(color, &end_color, particle.energy);
+            match ctx.lighting {
+                // bend the corner normals outwards so the flat quad shades
+                // like a ball
+                Some(lighting) => {
+                    let to_camera = (ctx.camera_position - particle.position).normalize_or_zero();
+                    let colors = [quad.0, quad.1, quad.2, quad.3].map(|corner| {
+                        let outwards = (corner - particle.position).normalize_or_zero();
+                        let normal = (to_camera + outwards * 0.8).normalize_or_zero();
+                        lighting.shade(tint, corner, normal)
+                    });
+                    quads.gradient_quad(&quad, uv, colors);
+                }
+                None => quads.quad(&quad, uv, tint),
+            }
+        });
+        quads.apply_fog(ctx, self.material.blend_mode);
+        quads.draw(Some(texture), self.material);
+    }
+
+    fn draw_mesh_particles(&self, style: &ParticleStyle, mesh: &ParticleMesh, ctx: &RenderContext) {
+        let mut meshes = self.meshes.borrow_mut();
+        meshes.clear();
+        self.for_each_in_draw_order(ctx, |index, particle| {
+            let (sin, cos) = (particle.variation * TAU).sin_cos();
+            let axis = vec3(cos, 0.6, sin).normalize();
+            let rotation = Quat::from_axis_angle(axis, particle.rotation);
+            let color = self.particle_color(style, index, &particle);
+            meshes.mesh(
+                mesh,
+                particle.position,
+                rotation,
+                particle.size,
+                color,
+                ctx.lighting,
             );
+        });
+        meshes.apply_fog(ctx, self.material.blend_mode);
+        meshes.draw(self.material);
+    }
+
+    fn draw_streak_particles(
+        &self,
+        style: &ParticleStyle,
+        stretch: f32,
+        width: f32,
+        ctx: &RenderContext,
+    ) {
+        let tail_color = |color: Color| Color { a: 0.0, ..color };
+        if width == 0.0 {
+            let mut batch = self.batch.borrow_mut();
+            batch.clear();
+            self.for_each_in_draw_order(ctx, |index, particle| {
+                let color = self.particle_color(style, index, &particle);
+                let tail = particle.position - particle.velocity * stretch;
+                batch.gradient_line(particle.position, tail, color, tail_color(color));
+            });
+            batch.apply_fog(ctx, self.material.blend_mode);
+            batch.draw(self.material);
+        } else {
+            let whole = Rect::new(0.0, 0.0, 1.0, 1.0);
+            let mut quads = self.quads.borrow_mut();
+            quads.clear();
+            self.for_each_in_draw_order(ctx, |index, particle| {
+                let color = self.particle_color(style, index, &particle);
+                let tail = particle.position - particle.velocity * stretch;
+                let quad = ParticleQuad::segment(particle.position, tail, width, width, ctx);
+                let tail = tail_color(color);
+                quads.gradient_quad(&quad, whole, [color, tail, tail, color]);
+            });
+            quads.apply_fog(ctx, self.material.blend_mode);
+            quads.draw(None, self.material);
         }
     }
 
     pub fn update(&mut self, delta: f32) {
-        for particle in &mut self.particles {
-            particle.prev_position = particle.position;
-            particle.position += particle.velocity * delta;
-            particle.energy -= delta; // reduction of timespan overtime
-            particle.velocity.y -= 9.8 * delta; // gravity
+        passes::step_all(
+            &mut self.particles.attributes_mut(),
+            &self.forces,
+            &self.colliders,
+            delta,
+            self.kernel,
+        );
+        self.particles
+            .retain(|particles, i| particles.energies()[i] > 0.0);
+
+        let limit = self.particle_limit();
+        let refuse = self.overflow_policy == OverflowPolicy::Refuse;
+        self.dropped = 0;
+        // whether a particle born this frame already used up its lifetime
+        let mut stillborn = false;
+
+        for emitter in &mut self.emitters {
+            if !emitter.enabled {
+                continue;
+            }
+            let (previous, current) = emitter.step(delta);
+            let rotation = emitter.rotation;
+
+            let mut count = emitter.spawn_per_update;
+            if let Some(limit) = limit
+                && refuse
+            {
+                let room = limit.saturating_sub(self.particles.len());
+                self.dropped += count.saturating_sub(room);
+                count = count.min(room);
+            }
+
+            let first_new = self.particles.len();
+            emitter.emitter.spawn_into(
+                count,
+                self.particle_size,
+                &self.rng,
+                &mut self.particles,
+                &mut self.scratch_points,
+            );
+            let count = self.particles.len() - first_new;
+
+            // Spread the frame's spawns over its duration: particle `i` is born at
+            // fraction `t` of the frame, at the emitter position for that moment,
+            // and has already lived for the rest of the frame.
+            let mut attrs = self.particles.attributes_mut();
+            for i in 0..count {
+                let t = (i as f32 + 0.5) / count as f32;
+                let origin = previous.lerp(current, t);
+                let index = first_new + i;
+                let position = origin + rotation * (attrs.positions[index] - current);
+                attrs.positions[index] = position;
+                attrs.prev_positions[index] = position;
+                attrs.velocities[index] = rotation * attrs.velocities[index];
+                passes::step_range(
+                    &mut attrs,
+                    index..index + 1,
+                    &self.forces,
+                    &self.colliders,
+                    (1.0 - t) * delta,
+                );
+                stillborn |= attrs.energies[index] <= 0.0;
+            }
+        }
+        if stillborn {
+            self.particles
+                .retain(|particles, i| particles.energies()[i] > 0.0);
+        }
+
+        if let Some(limit) = limit
+            && self.particles.len() > limit
+        {
+            self.dropped += self.particles.len() - limit;
+            self.enforce_limit(limit);
+        }
+
+        if let Some(trail) = &self.trail {
+            self.particles.record_trails(trail.spacing);
+        }
+    }
+
+    /// Shrink the pool to `limit` particles according to the overflow policy.
+    fn enforce_limit(&mut self, limit: usize) {
+        let particles = &mut self.particles;
+        let policy = self.overflow_policy;
+
+        // larger key = killed first; refusing only gets here when the limit
+        // was lowered, and then drops the newest particles
+        let key = |particles: &ParticleStorage, i: usize| match policy {
+            OverflowPolicy::Refuse => -particles.ages()[i],
+            OverflowPolicy::KillOldest => particles.ages()[i],
+            OverflowPolicy::KillLeastEnergy => -particles.energies()[i],
+        };
+
+        // find the key of the `excess`-th particle to kill, then kill everything
+        // above it, and as many at exactly that key as are still needed
+        let excess = particles.len() - limit;
+        let keys = &mut self.scratch_keys;
+        keys.clear();
+        keys.extend((0..particles.len()).map(|i| key(particles, i)));
+        let (_, &mut threshold, _) = keys.select_nth_unstable_by(excess - 1, |a, b| b.total_cmp(a));
+
+        let mut killed = 0;
+        particles.retain(|particles, i| {
+            let kill = key(particles, i) > threshold;
+            killed += kill as usize;
+            !kill
+        });
+        particles.retain(|particles, i| {
+            let kill = killed < excess && key(particles, i) == threshold;
+            killed += kill as usize;
+            !kill
+        });
+    }
+}
+
+/// Colour of a particle with `energy` left, fading from `start` to `end`.
+fn lifetime_color(start: &Color, end: &Color, energy: f32) -> Color {
+    // energy ranges from 1.0 -> 0.0; progress = 1 - energy
+    let t = (1.0 - energy).clamp(0.0, 1.0);
+    let r = start.r + (end.r - start.r) * t;
+    let g = start.g + (end.g - start.g) * t;
+    let b = start.b + (end.b - start.b) * t;
+    let a = start.a + (end.a - start.a) * t;
+    Color::new(r, g, b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Counts heap allocations per thread, so tests running in parallel
+    /// don't see each other's.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.alloc(layout) }
         }
 
-        let new_particles = self.emitter.spawn(self.spawn_per_update);
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
 
-        self.particles.extend(new_particles);
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(Cell::get)
+    }
+
+    fn seeded(seed: u64) -> ParticleSystem {
+        ParticleSystem::new()
+            .sphere(vec3(1.0, 2.0, 3.0), 2.0, Spawn::Volume)
+            .spawn_rate(7)
+            .seed(seed)
+    }
+
+    fn run(seed: u64, deltas: &[f32]) -> ParticleSystem {
+        let mut system = seeded(seed);
+        for &delta in deltas {
+            system.update(delta);
+        }
+        system
+    }
 
-        self.particles.retain(|particle| particle.energy > 0.0);
+    fn state_bits(system: &ParticleSystem) -> Vec<[u32; 12]> {
+        system
+            .particles
+            .iter()
+            .map(|p| {
+                [
+                    p.position.x.to_bits(),
+                    p.position.y.to_bits(),
+                    p.position.z.to_bits(),
+                    p.prev_position.x.to_bits(),
+                    p.prev_position.y.to_bits(),
+                    p.prev_position.z.to_bits(),
+                    p.velocity.x.to_bits(),
+                    p.velocity.y.to_bits(),
+                    p.velocity.z.to_bits(),
+                    p.energy.to_bits(),
+                    p.size.to_bits(),
+                    p.age.to_bits(),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_is_bit_identical() {
+        let deltas = [0.016, 0.017, 0.033, 0.008, 0.016, 0.1, 0.016];
+        let a = run(42, &deltas);
+        // stepping another system in between every update must not disturb
+        // the sequence
+        let mut b = seeded(42);
+        let mut other = seeded(7);
+        for &delta in &deltas {
+            b.update(delta);
+            other.update(delta);
+        }
+        assert!(!other.particles.is_empty());
+        assert!(!a.particles.is_empty());
+        assert_eq!(state_bits(&a), state_bits(&b));
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        let deltas = [0.016; 5];
+        assert_ne!(state_bits(&run(1, &deltas)), state_bits(&run(2, &deltas)));
+    }
+
+    #[test]
+    fn long_frames_never_leave_dead_particles() {
+        // spawns are spread over the frame, so only the latest ones live
+        let mut system = seeded(3);
+        system.update(3.0);
+        assert!(system.particles.iter().all(|p| p.energy > 0.0));
+        assert!((1..7).contains(&system.particle_count()));
+    }
+
+    #[test]
+    fn spawns_spread_along_a_moving_emitter() {
+        let mut system = ParticleSystem::new()
+            .point(Vec3::ZERO, Direction::Random, 0.0)
+            .spawn_rate(10)
+            .forces(vec![])
+            .seed(4);
+        system.update(0.01);
+        assert_eq!(system.particle_count(), 10);
+
+        system.emitters_mut()[0].set_position(vec3(10.0, 0.0, 0.0));
+        system.update(0.01);
+        let mut xs: Vec<f32> = system
+            .particles
+            .iter()
+            .skip(10)
+            .map(|p| p.position.x)
+            .collect();
+        xs.sort_by(f32::total_cmp);
+        assert_eq!(xs.len(), 10);
+        // one newborn per tenth of the segment, each drifted at most
+        // speed * delta from where it was born
+        for (i, x) in xs.iter().enumerate() {
+            let born = i as f32 + 0.5;
+            assert!((x - born).abs() <= 0.02 + 1e-4, "{xs:?}");
+        }
+    }
+
+    #[test]
+    fn snapshots_carry_trails() {
+        let mut system = seeded(5).trails(Trail::new(4));
+        let mut view = system.render_view();
+        let mut snapshot = Snapshot::new();
+        for _ in 0..6 {
+            system.update(0.016);
+        }
+        system.snapshot_into(&mut snapshot);
+        view.show_snapshot(&mut snapshot);
+
+        assert!(view.trail.is_some());
+        assert_eq!(view.particle_count(), system.particle_count());
+        for i in 0..system.particle_count() {
+            assert!(view.particles.trail(i).eq(system.particles.trail(i)));
+        }
+        assert_eq!(view.particles.trail(0).len(), 4);
+    }
+
+    #[test]
+    fn drawn_trails_start_at_the_particle() {
+        let mut system = seeded(6).trails(Trail::new(5));
+        for _ in 0..8 {
+            system.update(0.016);
+        }
+        let mut full = 0;
+        for (i, particle) in system.particles.iter().enumerate() {
+            let mut batch = LineBatch::new();
+            trail::line(
+                &mut batch,
+                particle.position,
+                system.particles.trail(i),
+                WHITE,
+            );
+            let recorded = system.particles.trail(i).len();
+            full += (recorded == 5) as usize;
+            // the newest recorded point is the particle itself, so `recorded`
+            // points make one segment fewer, the first leaving the particle
+            // at full alpha
+            let Some(mesh) = batch.meshes().first() else {
+                assert!(recorded < 2);
+                continue;
+            };
+            assert_eq!(mesh.vertices.len(), 2 * (recorded - 1));
+            let [start, end] = [0, 1].map(|v| mesh.vertices[v].position);
+            assert_eq!(start, particle.position);
+            assert!(start.distance(end) > 0.0);
+            assert_eq!(mesh.vertices[0].color[3], 255);
+        }
+        assert!(full > 0);
+    }
+
+    #[test]
+    fn emitters_share_one_pool() {
+        let mut system = ParticleSystem::new()
+            .sphere(Vec3::ZERO, 1.0, Spawn::Volume)
+            .cube(Vec3::ZERO, 1.0, Spawn::Surface)
+            .spawn_rate(3)
+            .seed(5);
+        system.update(0.01);
+        assert_eq!(system.particles.len(), 6);
+
+        system.emitter_mut("sphere 0").unwrap().set_enabled(false);
+        system.update(0.01);
+        assert_eq!(system.particles.len(), 9);
+
+        assert!(system.remove_emitter("cube 1").is_some());
+        assert!(system.remove_emitter("cube 1").is_none());
+        system.update(0.01);
+        assert_eq!(system.particles.len(), 9);
+
+        // names aren't reused after a removal
+        let system = system.cube(Vec3::ZERO, 1.0, Spawn::Volume);
+        let names: Vec<_> = system.emitters().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["sphere 0", "cube 2"]);
+    }
+
+    fn capped(policy: OverflowPolicy) -> ParticleSystem {
+        ParticleSystem::new()
+            .sphere(Vec3::ZERO, 1.0, Spawn::Volume)
+            .spawn_rate(10)
+            .max_particles(25)
+            .overflow_policy(policy)
+            .seed(3)
+    }
+
+    #[test]
+    fn refuse_stops_spawning_at_cap() {
+        let mut system = capped(OverflowPolicy::Refuse);
+        system.update(0.01);
+        system.update(0.01);
+        assert_eq!(system.dropped_count(), 0);
+        system.update(0.01);
+        assert_eq!(system.particle_count(), 25);
+        assert_eq!(system.dropped_count(), 5);
+        // only the 5 particles that still fit were spawned on the last update
+        assert_eq!(system.particles.iter().filter(|p| p.age < 0.01).count(), 5);
+
+        // lowering the limit drops the newest, wherever compaction put them
+        system.set_max_particles(Some(15));
+        system.update(0.01);
+        assert_eq!(system.particle_count(), 15);
+        assert_eq!(
+            system
+                .particles
+                .ages()
+                .iter()
+                .filter(|&&a| a > 0.03)
+                .count(),
+            10
+        );
+    }
+
+    #[test]
+    fn kill_oldest_keeps_youngest() {
+        let mut system = capped(OverflowPolicy::KillOldest);
+        for _ in 0..3 {
+            system.update(0.01);
+        }
+        assert_eq!(system.particle_count(), 25);
+        assert_eq!(system.dropped_count(), 5);
+        // all 10 newborns of the last update survived
+        assert_eq!(system.particles.iter().filter(|p| p.age < 0.01).count(), 10);
+    }
+
+    #[test]
+    fn kill_least_energy_keeps_most_energetic() {
+        let mut system = capped(OverflowPolicy::KillLeastEnergy);
+        for _ in 0..3 {
+            system.update(0.01);
+        }
+        assert_eq!(system.particle_count(), 25);
+        assert_eq!(system.dropped_count(), 5);
+        // newborns have the most energy left, so all 10 survived
+        assert_eq!(
+            system.particles.iter().filter(|p| p.energy > 0.99).count(),
+            10
+        );
+    }
+
+    #[test]
+    fn steady_state_updates_do_not_allocate() {
+        let mut system = ParticleSystem::new()
+            .point(Vec3::ZERO, Direction::Fixed(vec3(0.0, 1.0, 0.0)), 0.4)
+            .sphere(Vec3::ZERO, 2.0, Spawn::Even)
+            .cube(Vec3::ZERO, 2.0, Spawn::Even)
+            .spawn_rate(20)
+            .max_particles(2000)
+            .overflow_policy(OverflowPolicy::KillOldest)
+            .seed(11);
+        // particles live one second; run long enough for births and deaths to balance
+        for _ in 0..200 {
+            system.update(0.016);
+        }
+
+        let before = allocations();
+        for _ in 0..200 {
+            system.update(0.016);
+        }
+        assert_eq!(allocations() - before, 0);
+        assert!(system.particle_count() > 0);
     }
 }
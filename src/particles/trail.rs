@@ -0,0 +1,132 @@
+use crate::particles::{
+    batch::{LineBatch, QuadBatch},
+    render::RenderContext,
+    utils::ParticleQuad,
+};
+use macroquad::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrailStyle {
+    /// A thin polyline.
+    Line,
+    /// A camera-facing strip that narrows towards its end.
+    Ribbon,
+}
+
+/// Trails drawn behind every particle of a system, fading out over their
+/// length.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Trail {
+    /// Number of positions kept per particle, its current one included.
+    pub length: usize,
+    /// Minimum distance between recorded positions; 0 records one every
+    /// update, so the trail covers a fixed time instead of a fixed distance.
+    pub spacing: f32,
+    /// Width of a ribbon at the particle.
+    pub width: f32,
+    pub style: TrailStyle,
+}
+
+impl Trail {
+    /// A polyline through the last `length` positions.
+    pub fn new(length: usize) -> Self {
+        Self {
+            length,
+            spacing: 0.0,
+            width: 0.1,
+            style: TrailStyle::Line,
+        }
+    }
+
+    pub fn spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    /// Draw as a ribbon `width` wide at the particle.
+    pub fn ribbon(mut self, width: f32) -> Self {
+        self.width = width;
+        self.style = TrailStyle::Ribbon;
+        self
+    }
+}
+
+/// `color` faded by how far along a trail of `points` points `i` lies.
+fn fade(color: Color, i: usize, points: usize) -> Color {
+    let t = i as f32 / (points - 1) as f32;
+    Color {
+        a: color.a * (1.0 - t),
+        ..color
+    }
+}
+
+/// `history` without its newest point if that is `head` itself, as it is
+/// after every update that recorded the particle's position.
+fn behind(
+    head: Vec3,
+    history: impl ExactSizeIterator<Item = Vec3>,
+) -> impl ExactSizeIterator<Item = Vec3> {
+    let mut history = history.peekable();
+    history.next_if_eq(&head);
+    history
+}
+
+/// Add a polyline from `head` through `history`, newest first.
+pub(crate) fn line(
+    batch: &mut LineBatch,
+    head: Vec3,
+    history: impl ExactSizeIterator<Item = Vec3>,
+    color: Color,
+) {
+    let history = behind(head, history);
+    let points = history.len() + 1;
+    let mut previous = head;
+    for (i, point) in history.enumerate() {
+        batch.gradient_line(
+            previous,
+            point,
+            fade(color, i, points),
+            fade(color, i + 1, points),
+        );
+        previous = point;
+    }
+}
+
+/// Add a ribbon from `head` through `history`, newest first, `width` wide
+/// at the head and narrowing to nothing at the end.
+pub(crate) fn ribbon(
+    batch: &mut QuadBatch,
+    head: Vec3,
+    history: impl ExactSizeIterator<Item = Vec3>,
+    width: f32,
+    color: Color,
+    ctx: &RenderContext,
+) {
+    let history = behind(head, history);
+    let points = history.len() + 1;
+    let whole = Rect::new(0.0, 0.0, 1.0, 1.0);
+    let mut previous = head;
+    for (i, point) in history.enumerate() {
+        let near = width * (1.0 - i as f32 / (points - 1) as f32);
+        let far = width * (1.0 - (i + 1) as f32 / (points - 1) as f32);
+        let (near_color, far_color) = (fade(color, i, points), fade(color, i + 1, points));
+        batch.gradient_quad(
+            &ParticleQuad::segment(previous, point, near, far, ctx),
+            whole,
+            [near_color, far_color, far_color, near_color],
+        );
+        previous = point;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trails_fade_towards_their_end() {
+        assert_eq!(fade(WHITE, 0, 5).a, 1.0);
+        assert_eq!(fade(WHITE, 2, 5).a, 0.5);
+        assert_eq!(fade(WHITE, 4, 5).a, 0.0);
+    }
+}
@@ -1,20 +1,55 @@
+use crate::particles::render::RenderContext;
 use macroquad::prelude::*;
 
+#[derive(Clone, Copy)]
 pub enum Direction {
     Fixed(Vec3),
     Random,
 }
 
+/// Corners of a particle's quad: bottom-left, bottom-right, top-right,
+/// top-left.
 pub struct ParticleQuad(pub Vec3, pub Vec3, pub Vec3, pub Vec3);
 
+impl ParticleQuad {
+    /// Camera-facing square of edge `size` centred on `center`, turned by
+    /// `rotation` radians (counter-clockwise on screen).
+    pub fn billboard(center: Vec3, size: f32, rotation: f32, ctx: &RenderContext) -> Self {
+        let (sin, cos) = rotation.sin_cos();
+        let half = size * 0.5;
+        let right = (ctx.right * cos + ctx.up * sin) * half;
+        let up = (ctx.up * cos - ctx.right * sin) * half;
+        Self(
+            center - right - up,
+            center + right - up,
+            center + right + up,
+            center - right + up,
+        )
+    }
+
+    /// Camera-facing strip from `start` to `end`, `start_width` wide at
+    /// `start` and `end_width` at `end`. Corners run start, end, end, start.
+    pub fn segment(
+        start: Vec3,
+        end: Vec3,
+        start_width: f32,
+        end_width: f32,
+        ctx: &RenderContext,
+    ) -> Self {
+        let side = (end - start)
+            .cross(ctx.camera_position - start)
+            .normalize_or_zero();
+        let near = side * start_width * 0.5;
+        let far = side * end_width * 0.5;
+        Self(start - near, end - far, end + far, start + near)
+    }
+}
+
+#[derive(Clone, Copy)]
 pub enum Spawn {
     Volume,
     Surface,
-}
-
-pub fn random_direction() -> Vec3 {
-    let x = rand::gen_range(-1.0, 1.0);
-    let y = rand::gen_range(-1.0, 1.0);
-    let z = rand::gen_range(-1.0, 1.0);
-    vec3(x, y, z).normalize()
+    /// Surface points spaced apart with Poisson-disk sampling, so a frame's
+    /// batch doesn't clump.
+    Even,
 }
@@ -0,0 +1,294 @@
+use crate::particles::emitter::{Emitter, NamedEmitter};
+use crate::particles::fog::Fog;
+use crate::particles::manager::{ParticleManager, SystemHandle};
+use crate::particles::material::BlendMode;
+use crate::particles::system::{OverflowPolicy, ParticleStyle, ParticleSystem};
+use crate::particles::utils::{Direction, Spawn};
+use macroquad::prelude::*;
+
+use super::{CameraController, Scene, SceneName};
+
+/// A fire system with several emitters (flames, embers, sparks) feeding one
+/// particle pool, plus a separately styled smoke system. The panel toggles
+/// systems and adds or removes fire emitters at runtime.
+pub struct CampfireScene {
+    particles: ParticleManager,
+    camera: CameraController,
+    sparks_added: usize,
+    // global particle budget (0 = unlimited) and the policy all systems use to meet it
+    budget: f32,
+    overflow_policy: OverflowPolicy,
+}
+
+impl CampfireScene {
+    pub fn new() -> Self {
+        Self {
+            particles: ParticleManager::new(),
+            camera: CameraController::new(vec3(0.0, -3.0, 0.0), 14.0),
+            sparks_added: 0,
+            budget: 0.0,
+            overflow_policy: OverflowPolicy::KillOldest,
+        }
+    }
+}
+
+impl Scene for CampfireScene {
+    fn start(&mut self) {
+        let style = ParticleStyle::ColorGradient(YELLOW, Color::new(0.4, 0.05, 0.0, 0.0));
+        let up = Direction::Fixed(vec3(0.0, 1.0, 0.0));
+
+        self.particles.add_system(
+            "fire",
+            ParticleSystem::new()
+                .emitter(
+                    NamedEmitter::new(
+                        "flames",
+                        Emitter::Sphere {
+                            position: vec3(0.0, -4.5, 0.0),
+                            size: 0.8,
+                            spawn_type: Spawn::Volume,
+                        },
+                    )
+                    .spawn_rate(8),
+                )
+                .emitter(
+                    NamedEmitter::new(
+                        "embers",
+                        Emitter::Point {
+                            position: vec3(0.0, -4.3, 0.0),
+                            direction: up,
+                            spread: 0.35,
+                        },
+                    )
+                    .spawn_rate(3),
+                )
+                .style(style)
+                // flames add light; without depth writes they don't hide each other
+                .blend_mode(BlendMode::Additive)
+                .depth_write(false)
+                .overflow_policy(self.overflow_policy),
+        );
+
+        self.particles.add_system(
+            "smoke",
+            ParticleSystem::new()
+                .emitter(
+                    NamedEmitter::new(
+                        "plume",
+                        Emitter::Point {
+                            position: vec3(0.0, -3.5, 0.0),
+                            direction: Direction::Fixed(vec3(0.0, 1.0, 0.0)),
+                            spread: 0.8,
+                        },
+                    )
+                    // lean the plume as if there were a light wind
+                    .rotation(Quat::from_rotation_z(-0.3))
+                    .spawn_rate(2),
+                )
+                .style(ParticleStyle::ColorGradient(
+                    Color::new(0.5, 0.5, 0.5, 0.6),
+                    Color::new(0.2, 0.2, 0.2, 0.0),
+                ))
+                .blend_mode(BlendMode::Alpha)
+                .depth_write(false)
+                .depth_sort(true)
+                .overflow_policy(self.overflow_policy),
+        );
+    }
+
+    fn stop(&mut self) {
+        self.particles = ParticleManager::new();
+    }
+
+    fn update(&mut self) -> Option<SceneName> {
+        self.camera.update();
+
+        let delta = get_frame_time();
+        self.particles.update(delta);
+
+        use macroquad::ui::{hash, root_ui, widgets};
+
+        let panel_w = 260.0;
+        let panel_pos = vec2(screen_width() - (panel_w + 20.0), 20.0);
+
+        let mut toggle_system: Option<SystemHandle> = None;
+        let mut remove_system: Option<SystemHandle> = None;
+        let mut toggle: Option<String> = None;
+        let mut remove: Option<String> = None;
+        let mut add_sparks = false;
+        let mut next_policy = false;
+
+        let particles = &self.particles;
+        let fire = particles
+            .find("fire")
+            .and_then(|handle| particles.get(handle));
+        widgets::Window::new(
+            hash!(screen_width() as i32, screen_height() as i32),
+            panel_pos,
+            vec2(panel_w, 420.0),
+        )
+        .label("Campfire")
+        .titlebar(true)
+        .ui(&mut root_ui(), |ui| {
+            ui.label(None, "Particle budget (0 = unlimited)");
+            ui.slider(hash!(), "Budget", 0.0f32..2000.0f32, &mut self.budget);
+            ui.label(
+                None,
+                &format!("Overflow: {}", policy_label(self.overflow_policy)),
+            );
+            if ui.button(None, "Next Overflow Policy") {
+                next_policy = true;
+            }
+            ui.separator();
+
+            ui.label(None, "Systems:");
+            for handle in particles.handles() {
+                let name = particles.name(handle).unwrap_or_default();
+                let state = if particles.is_enabled(handle) {
+                    "on"
+                } else {
+                    "off"
+                };
+                let count = particles.get(handle).map_or(0, |s| s.particle_count());
+                ui.label(None, &format!("{name} ({state}): {count} particles"));
+                if ui.button(None, format!("Toggle system {name}")) {
+                    toggle_system = Some(handle);
+                }
+                if ui.button(None, format!("Remove system {name}")) {
+                    remove_system = Some(handle);
+                }
+            }
+            ui.separator();
+
+            if let Some(system) = fire {
+                ui.label(None, "Fire emitters:");
+                for emitter in system.emitters() {
+                    let state = if emitter.enabled { "on" } else { "off" };
+                    let pos = emitter.position();
+                    ui.label(
+                        None,
+                        &format!(
+                            "{} ({state}) at {:.1}, {:.1}, {:.1}",
+                            emitter.name, pos.x, pos.y, pos.z
+                        ),
+                    );
+                    if ui.button(None, format!("Toggle {}", emitter.name)) {
+                        toggle = Some(emitter.name.clone());
+                    }
+                    if ui.button(None, format!("Remove {}", emitter.name)) {
+                        remove = Some(emitter.name.clone());
+                    }
+                    ui.separator();
+                }
+                if ui.button(None, "Add sparks") {
+                    add_sparks = true;
+                }
+            }
+        });
+
+        let budget = self.budget.round() as usize;
+        self.particles
+            .set_budget(if budget == 0 { None } else { Some(budget) });
+        if next_policy {
+            self.overflow_policy = match self.overflow_policy {
+                OverflowPolicy::Refuse => OverflowPolicy::KillOldest,
+                OverflowPolicy::KillOldest => OverflowPolicy::KillLeastEnergy,
+                OverflowPolicy::KillLeastEnergy => OverflowPolicy::Refuse,
+            };
+            let handles: Vec<SystemHandle> = self.particles.handles().collect();
+            for handle in handles {
+                if let Some(system) = self.particles.get_mut(handle) {
+                    system.set_overflow_policy(self.overflow_policy);
+                }
+            }
+        }
+
+        if let Some(handle) = toggle_system {
+            let enabled = self.particles.is_enabled(handle);
+            self.particles.set_enabled(handle, !enabled);
+        }
+
+        if let Some(handle) = remove_system {
+            self.particles.remove_system(handle);
+        }
+
+        if let Some(system) = self
+            .particles
+            .find("fire")
+            .and_then(|handle| self.particles.get_mut(handle))
+        {
+            if let Some(name) = toggle
+                && let Some(emitter) = system.emitter_mut(&name)
+            {
+                let enabled = !emitter.enabled;
+                emitter.set_enabled(enabled);
+            }
+            if let Some(name) = remove {
+                system.remove_emitter(&name);
+            }
+            if add_sparks {
+                self.sparks_added += 1;
+                let angle = self.sparks_added as f32 * 2.4;
+                system.add_emitter(
+                    NamedEmitter::new(
+                        format!("sparks {}", self.sparks_added),
+                        Emitter::Cube {
+                            position: vec3(angle.cos() * 1.2, -4.6, angle.sin() * 1.2),
+                            size: 0.3,
+                            spawn_type: Spawn::Surface,
+                        },
+                    )
+                    .spawn_rate(4),
+                );
+            }
+        }
+
+        if let Some(scene) = self.handle_back() {
+            return Some(scene);
+        }
+
+        None
+    }
+
+    fn draw(&self) {
+        let night = Color::new(0.03, 0.03, 0.05, 1.0);
+        clear_background(night);
+
+        set_camera(&self.camera.camera());
+
+        // the far side of the room fades into the night
+        let ctx = self
+            .camera
+            .render_context()
+            .with_fog(Fog::exponential(night, 0.04));
+        self.draw_room(&ctx);
+        self.particles.draw(&ctx);
+
+        set_default_camera();
+
+        // HUD: particle count against the budget
+        let count = self.particles.particle_count();
+        let hud = match self.particles.budget() {
+            Some(budget) => format!("Particles: {count} / {budget}"),
+            None => format!("Particles: {count}"),
+        };
+        draw_text(&hud, 10.0, 60.0, 24.0, WHITE);
+        if self.particles.budget_exceeded() {
+            draw_text(
+                &format!("Budget exceeded ({})", policy_label(self.overflow_policy)),
+                10.0,
+                84.0,
+                24.0,
+                RED,
+            );
+        }
+    }
+}
+
+fn policy_label(policy: OverflowPolicy) -> &'static str {
+    match policy {
+        OverflowPolicy::Refuse => "refuse to spawn",
+        OverflowPolicy::KillOldest => "kill oldest",
+        OverflowPolicy::KillLeastEnergy => "kill least energy",
+    }
+}
@@ -15,6 +15,7 @@ impl MenuScene {
                 (SceneName::CubeEmitter, "Cube Emitter"),
                 (SceneName::SphereEmitter, "Sphere Emitter"),
                 (SceneName::UnifiedEmitter, "Unified Emitter"),
+                (SceneName::Campfire, "Campfire"),
             ],
         }
     }
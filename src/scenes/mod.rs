@@ -1,10 +1,24 @@
+mod campfire_scene;
 mod cube_emitter_scene;
 mod menu_scene;
 mod point_emitter_scene;
 mod sphere_emitter_scene;
 mod unified_emitter_scene;
 
+use crate::particles::batch::LineBatch;
+use crate::particles::material::{BlendMode, MaterialSettings};
+use crate::particles::render::RenderContext;
 use macroquad::prelude::*;
+use std::cell::RefCell;
+
+/// Height of the floor `Scene::draw_room` draws.
+pub const FLOOR_Y: f32 = -5.0;
+
+thread_local! {
+    /// Lines of `Scene::draw_room`, kept between frames to reuse their
+    /// buffers.
+    static ROOM_BATCH: RefCell<LineBatch> = RefCell::new(LineBatch::new());
+}
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum SceneName {
@@ -13,6 +27,7 @@ pub enum SceneName {
     CubeEmitter,
     SphereEmitter,
     UnifiedEmitter,
+    Campfire,
 }
 
 pub trait Scene {
@@ -21,41 +36,64 @@ pub trait Scene {
     fn update(&mut self) -> Option<SceneName>;
     fn draw(&self);
 
-    /// Draw a simple wireframe floor and grid to give a 3D room feel.
-    /// Scenes can call `self.draw_room()` after setting a 3D camera.
-    fn draw_room(&self) {
+    /// Draw a simple wireframe floor and grid to give a 3D room feel,
+    /// fogged like the particles if `ctx` has fog.
+    /// Scenes can call `self.draw_room(ctx)` after setting a 3D camera.
+    fn draw_room(&self, ctx: &RenderContext) {
         use macroquad::prelude::*;
 
         let grid_size = 20.0; // half-size in world units
         let grid_step = 1.0; // spacing between grid lines
-        let y = -5.0; // vertical position of the floor/grid
+        let y = FLOOR_Y;
 
         // subtle grid color
         let grid_color = Color::new(0.7, 0.7, 0.7, 0.35);
         let axis_color = Color::new(1.0, 0.2, 0.2, 0.9);
 
-        let mut x = -grid_size;
-        while x <= grid_size {
-            draw_line_3d(vec3(x, y, -grid_size), vec3(x, y, grid_size), grid_color);
-            x += grid_step;
-        }
+        ROOM_BATCH.with_borrow_mut(|batch| {
+            batch.clear();
+            // with fog, lines are split at every crossing so it can vary
+            // along them
+            let split = ctx.fog.is_some();
+            let mut line = |start: Vec3, end: Vec3, color: Color| {
+                let steps = if split {
+                    (start.distance(end) / grid_step).round().max(1.0) as usize
+                } else {
+                    1
+                };
+                for i in 0..steps {
+                    let a = start.lerp(end, i as f32 / steps as f32);
+                    let b = start.lerp(end, (i + 1) as f32 / steps as f32);
+                    batch.line(a, b, color);
+                }
+            };
 
-        let mut z = -grid_size;
-        while z <= grid_size {
-            draw_line_3d(vec3(-grid_size, y, z), vec3(grid_size, y, z), grid_color);
-            z += grid_step;
-        }
+            let mut x = -grid_size;
+            while x <= grid_size {
+                line(vec3(x, y, -grid_size), vec3(x, y, grid_size), grid_color);
+                x += grid_step;
+            }
 
-        draw_line_3d(
-            vec3(-grid_size, y, 0.0),
-            vec3(grid_size, y, 0.0),
-            axis_color,
-        );
-        draw_line_3d(
-            vec3(0.0, y, -grid_size),
-            vec3(0.0, y, grid_size),
-            axis_color,
-        );
+            let mut z = -grid_size;
+            while z <= grid_size {
+                line(vec3(-grid_size, y, z), vec3(grid_size, y, z), grid_color);
+                z += grid_step;
+            }
+
+            line(
+                vec3(-grid_size, y, 0.0),
+                vec3(grid_size, y, 0.0),
+                axis_color,
+            );
+            line(
+                vec3(0.0, y, -grid_size),
+                vec3(0.0, y, grid_size),
+                axis_color,
+            );
+
+            batch.apply_fog(ctx, BlendMode::Alpha);
+            batch.draw(MaterialSettings::default());
+        });
     }
 
     /// Default helper to draw a Back button (and handle Escape key) for scenes.
@@ -88,6 +126,7 @@ pub fn create_scene(name: SceneName) -> Box<dyn Scene> {
         SceneName::CubeEmitter => Box::new(cube_emitter_scene::CubeEmitterScene::new()),
         SceneName::SphereEmitter => Box::new(sphere_emitter_scene::SphereEmitterScene::new()),
         SceneName::UnifiedEmitter => Box::new(unified_emitter_scene::UnifiedEmitterScene::new()),
+        SceneName::Campfire => Box::new(campfire_scene::CampfireScene::new()),
     }
 }
 
@@ -188,4 +227,9 @@ impl CameraController {
             ..Default::default()
         }
     }
+
+    /// View basis of `camera()`, for drawing camera-facing particles.
+    pub fn render_context(&self) -> RenderContext<'static> {
+        RenderContext::from_camera(&self.camera())
+    }
 }
@@ -57,10 +57,11 @@ impl Scene for SphereEmitterScene {
         set_camera(&self.camera.camera());
 
         // draw a simple wireframe floor/grid for 3D context
-        self.draw_room();
+        let ctx = self.camera.render_context();
+        self.draw_room(&ctx);
 
         if let Some(system) = &self.particle_system {
-            system.draw();
+            system.draw(&ctx);
         }
 
         set_default_camera();
@@ -1,16 +1,54 @@
+use crate::particles::colormap::{ColorAttribute, ColorRange, Colormap};
+use crate::particles::field::{FieldOverlay, FieldRegion};
+use crate::particles::flipbook::{Flipbook, Playback};
+use crate::particles::fog::Fog;
+use crate::particles::force::{Collider, Force};
+use crate::particles::lighting::{Lighting, PointLight};
+use crate::particles::material::BlendMode;
+use crate::particles::mesh::ParticleMesh;
+use crate::particles::passes::Kernel;
+use crate::particles::path::{Keyframe, MotionPath, PathMode};
+use crate::particles::shadow::BlobShadows;
+use crate::particles::simulation::SimulationThread;
 use crate::particles::system::{ParticleStyle, ParticleSystem};
+use crate::particles::trail::Trail;
 use crate::particles::utils::Spawn;
 use macroquad::prelude::*;
+use std::sync::Arc;
 
-use super::{CameraController, Scene, SceneName};
+use super::{CameraController, FLOOR_Y, Scene, SceneName};
+
+/// Steps per second of the system when it runs on its own thread.
+const SIMULATION_RATE: f32 = 60.0;
+
+/// Background colour, which fog fades towards.
+const BACKGROUND: Color = Color::new(0.06, 0.06, 0.06, 1.0);
+
+/// A crystal shard for the OBJ debris look.
+const SHARD_OBJ: &str = "\
+v 0 1 0
+v 0.3 0 0.2
+v -0.3 0 0.2
+v 0 0 -0.35
+v 0 -0.6 0
+f 1 3 2
+f 1 2 4
+f 1 4 3
+f 5 2 3
+f 5 4 2
+f 5 3 4
+";
 
 pub struct UnifiedEmitterScene {
     particle_system: Option<ParticleSystem>,
+    /// Holds the system instead of `particle_system` while it runs on a
+    /// separate thread.
+    simulation: Option<SimulationThread>,
     camera: CameraController,
 
     // UI state
     emitter_index: usize, // 0: Point, 1: Cube, 2: Sphere
-    spawn_volume: bool,
+    spawn_index: usize,   // 0: Volume, 1: Surface, 2: Even
     size: f32,
     spread: f32,
     spawn_per_update: usize,
@@ -25,15 +63,62 @@ pub struct UnifiedEmitterScene {
     end_v: f32,
     show_color_picker: bool,
     picker_for_start: bool,
+    // Emitter motion: orbit around the origin to preview trails from a moving source
+    orbit_emitter: bool,
+    orbit_speed: f32,
+    orbit_angle: f32,
+    path_index: usize, // 0: None, 1: Keyframes, 2: Catmull-Rom, 3: Bezier
+    path_mode: PathMode,
+    path_speed: f32,
+    seed: u64,
+    seed_text: String,
+    max_particles: f32, // 0 = unlimited
+    gravity: f32,
+    drag: f32,
+    attractor: f32, // strength of an attractor at the origin; negative repels
+    floor_bounce: bool,
+    shadows: bool,
+    kernel: Kernel,
+    // 0: Crosses, 1: Billboards, 2: Flipbook, 3: Streak lines, 4: Streak quads,
+    // 5: Cubes, 6: Spheres, 7: OBJ shards
+    look_index: usize,
+    texture: Option<Texture2D>,
+    sheet: Option<Texture2D>,
+    playback: Playback,
+    // built on first use and shared by every rebuilt system
+    meshes: Option<[Arc<ParticleMesh>; 3]>,
+    attribute_index: usize, // 0: Speed, 1: Age, 2: Height, 3: Kinetic energy, 4: Density, 5: Vertical velocity
+    colormap: Colormap,
+    auto_range: bool,
+    range_max: f32,
+    // lighting of mesh and billboard particles, rebuilt from the sliders
+    lit: bool,
+    ambient: f32,
+    sun: f32,
+    sun_angle: f32,
+    floor_light: bool,
+    lighting: Lighting,
+    fog_index: usize,   // 0: Off, 1: Linear, 2: Exponential
+    field_index: usize, // 0: Off, 1: Grid, 2: Slice
+    streamlines: bool,
+    field: Option<FieldOverlay>,
+    // what the overlay draws while the system runs on its own thread
+    field_forces: Vec<Force>,
+    blend_mode: BlendMode,
+    depth_write: bool,
+    depth_test: bool,
+    depth_sort: bool,
+    trail_index: usize, // 0: Off, 1: Lines, 2: Ribbons
 }
 
 impl UnifiedEmitterScene {
     pub fn new() -> Self {
         Self {
             particle_system: None,
+            simulation: None,
             camera: CameraController::new(vec3(0.0, 0.0, 0.0), 20.0),
             emitter_index: 0,
-            spawn_volume: true,
+            spawn_index: 0,
             size: 4.0,
             spread: 0.3,
             spawn_per_update: 2,
@@ -47,11 +132,97 @@ impl UnifiedEmitterScene {
             end_v: 1.0,
             show_color_picker: false,
             picker_for_start: true,
+            orbit_emitter: false,
+            orbit_speed: 4.0,
+            orbit_angle: 0.0,
+            path_index: 0,
+            path_mode: PathMode::Loop,
+            path_speed: 1.0,
+            seed: 1,
+            seed_text: "1".to_string(),
+            max_particles: 0.0,
+            gravity: 9.8,
+            drag: 0.0,
+            attractor: 0.0,
+            floor_bounce: false,
+            shadows: false,
+            kernel: Kernel::Simd,
+            look_index: 0,
+            texture: None,
+            sheet: None,
+            playback: Playback::OverLifetime,
+            meshes: None,
+            attribute_index: 0,
+            colormap: Colormap::Viridis,
+            auto_range: true,
+            range_max: 10.0,
+            lit: true,
+            ambient: 0.35,
+            sun: 0.7,
+            sun_angle: 0.6,
+            floor_light: false,
+            lighting: Lighting::new(),
+            fog_index: 0,
+            field_index: 0,
+            streamlines: false,
+            field: None,
+            field_forces: vec![],
+            blend_mode: BlendMode::Alpha,
+            depth_write: true,
+            depth_test: true,
+            depth_sort: false,
+            trail_index: 0,
         }
     }
 
     fn rebuild_system(&mut self) {
-        let style = ParticleStyle::ColorGradient(self.start_color, self.end_color);
+        let (style, particle_size) = match self.look_index {
+            0 => {
+                let style = ParticleStyle::ColorGradient(self.start_color, self.end_color);
+                (style, 0.1)
+            }
+            1 => {
+                let texture = self.texture.get_or_insert_with(soft_dot_texture).clone();
+                let style = ParticleStyle::Texture {
+                    texture,
+                    color: self.start_color,
+                    flipbook: None,
+                };
+                (style, 0.6)
+            }
+            2 => {
+                let texture = self.sheet.get_or_insert_with(ring_sheet_texture).clone();
+                let style = ParticleStyle::Texture {
+                    texture,
+                    color: self.start_color,
+                    flipbook: Some(Flipbook::new(4, 4, 16.0, self.playback)),
+                };
+                (style, 0.8)
+            }
+            8 => (self.colormap_style(), 0.1),
+            look @ 5.. => {
+                let meshes = self.meshes.get_or_insert_with(|| {
+                    [
+                        Arc::new(ParticleMesh::cube()),
+                        Arc::new(ParticleMesh::sphere(6, 10)),
+                        Arc::new(ParticleMesh::from_obj(SHARD_OBJ).expect("shard OBJ is valid")),
+                    ]
+                });
+                let style = ParticleStyle::Mesh {
+                    mesh: Arc::clone(&meshes[look - 5]),
+                    color: self.start_color,
+                };
+                (style, 0.3)
+            }
+            look => {
+                let style = ParticleStyle::Streak {
+                    color: self.start_color,
+                    stretch: 0.05,
+                    width: if look == 3 { 0.0 } else { 0.1 },
+                };
+                (style, 0.1)
+            }
+        };
         let bounding_box = (vec3(-50.0, -50.0, -50.0), vec3(50.0, 50.0, 50.0));
 
         let system = match self.emitter_index {
@@ -60,31 +231,255 @@ impl UnifiedEmitterScene {
                 crate::particles::utils::Direction::Random,
                 self.spread,
             ),
-            1 => ParticleSystem::new().cube(
-                vec3(0.0, 0.0, 0.0),
-                self.size,
-                if self.spawn_volume {
-                    Spawn::Volume
-                } else {
-                    Spawn::Surface
-                },
-            ),
-            _ => ParticleSystem::new().sphere(
-                vec3(0.0, 0.0, 0.0),
-                self.size,
-                if self.spawn_volume {
-                    Spawn::Volume
-                } else {
-                    Spawn::Surface
-                },
-            ),
+            1 => ParticleSystem::new().cube(vec3(0.0, 0.0, 0.0), self.size, self.spawn_type()),
+            _ => ParticleSystem::new().sphere(vec3(0.0, 0.0, 0.0), self.size, self.spawn_type()),
         }
         .style(style)
+        .particle_size(particle_size)
+        .blend_mode(self.blend_mode)
+        .depth_write(self.depth_write)
+        .depth_test(self.depth_test)
+        .depth_sort(self.depth_sort)
         .bounding_box(bounding_box)
-        .spawn_rate(self.spawn_per_update);
+        .spawn_rate(self.spawn_per_update)
+        .seed(self.seed)
+        .forces(self.forces());
+        self.field_forces = self.forces();
+
+        let system = if self.floor_bounce {
+            // the room's floor
+            system.collider(Collider::Plane {
+                point: vec3(0.0, FLOOR_Y, 0.0),
+                normal: vec3(0.0, 1.0, 0.0),
+                restitution: 0.6,
+            })
+        } else {
+            system
+        };
+
+        let system = match self.particle_cap() {
+            Some(max) => system.max_particles(max),
+            None => system,
+        };
+
+        let system = match self.demo_path() {
+            Some(path) => system.path(path),
+            None => system,
+        };
+
+        let system = if self.shadows {
+            system.shadows(BlobShadows::new(FLOOR_Y))
+        } else {
+            system
+        };
+
+        let mut system = match self.trail_index {
+            1 => system.trails(Trail::new(20)),
+            2 => system.trails(Trail::new(20).spacing(0.2).ribbon(0.3)),
+            _ => system,
+        };
+        system.set_kernel(self.kernel);
+
+        if self.simulation.is_some() {
+            self.simulation = Some(SimulationThread::spawn(system, SIMULATION_RATE));
+        } else {
+            self.particle_system = Some(system);
+        }
+    }
+
+    fn fog(&self) -> Option<Fog> {
+        match self.fog_index {
+            1 => Some(Fog::linear(BACKGROUND, 15.0, 45.0)),
+            2 => Some(Fog::exponential(BACKGROUND, 0.05)),
+            _ => None,
+        }
+    }
+
+    fn update_lighting(&mut self) {
+        let (sin, cos) = self.sun_angle.sin_cos();
+        self.lighting.ambient = Color::new(self.ambient, self.ambient, self.ambient * 1.1, 1.0);
+        self.lighting.sun_direction = vec3(cos * 0.6, -1.0, sin * 0.6).normalize();
+        self.lighting.sun_color = Color::new(self.sun, self.sun, self.sun * 0.95, 1.0);
+        self.lighting.point_lights.clear();
+        if self.floor_light {
+            // a warm light on the room's floor
+            self.lighting.point_lights.push(PointLight {
+                position: vec3(0.0, -4.5, 0.0),
+                color: Color::new(1.5, 0.7, 0.2, 1.0),
+                range: 12.0,
+            });
+        }
+    }
+
+    /// Run `f` on the particle system, whichever thread it runs on. On the
+    /// simulation thread each call boxes `f` and sends it over a channel,
+    /// which is cheap enough to do every frame, as the orbit does.
+    fn with_system(&mut self, f: impl Fn(&mut ParticleSystem) + Send + 'static) {
+        if let Some(system) = &mut self.particle_system {
+            f(system);
+        } else if let Some(simulation) = &mut self.simulation {
+            simulation.apply(f);
+        }
+    }
+
+    /// Move the system between the render thread and its own thread.
+    fn toggle_simulation_thread(&mut self) {
+        if let Some(simulation) = self.simulation.take() {
+            self.particle_system = Some(simulation.stop());
+        } else if let Some(system) = self.particle_system.take() {
+            self.simulation = Some(SimulationThread::spawn(system, SIMULATION_RATE));
+        }
+    }
+
+    fn forces(&self) -> Vec<Force> {
+        vec![
+            Force::Gravity(vec3(0.0, -self.gravity, 0.0)),
+            Force::Drag(self.drag),
+            Force::Attractor {
+                position: Vec3::ZERO,
+                strength: self.attractor,
+            },
+        ]
+    }
 
-        self.particle_system = Some(system);
+    fn particle_cap(&self) -> Option<usize> {
+        let max = self.max_particles.round() as usize;
+        if max == 0 { None } else { Some(max) }
     }
+
+    fn spawn_type(&self) -> Spawn {
+        match self.spawn_index {
+            0 => Spawn::Volume,
+            1 => Spawn::Surface,
+            _ => Spawn::Even,
+        }
+    }
+
+    fn colormap_style(&self) -> ParticleStyle {
+        let range = if self.auto_range {
+            ColorRange::Auto
+        } else {
+            ColorRange::Manual {
+                min: 0.0,
+                max: self.range_max,
+            }
+        };
+        ParticleStyle::Colormap {
+            attribute: self.color_attribute(),
+            colormap: self.colormap,
+            range,
+        }
+    }
+
+    /// Overlay of the forces for the selected region, tracing streamlines
+    /// from a ring above the emitter if enabled.
+    fn field_overlay(&self) -> Option<FieldOverlay> {
+        let region = match self.field_index {
+            1 => FieldRegion::Grid {
+                min: vec3(-10.0, -4.0, -10.0),
+                max: vec3(10.0, 8.0, 10.0),
+                counts: uvec3(7, 5, 7),
+            },
+            2 => FieldRegion::Slice {
+                center: Vec3::ZERO,
+                normal: Vec3::Z,
+                size: 20.0,
+                count: 15,
+            },
+            _ => return None,
+        };
+        let seeds = if self.streamlines {
+            (0..8)
+                .map(|i| {
+                    let angle = i as f32 / 8.0 * std::f32::consts::TAU;
+                    vec3(angle.cos() * 9.0, 6.0, angle.sin() * 9.0)
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        let mut overlay = FieldOverlay::new(region).streamlines(seeds);
+        overlay.arrow_length = 1.5;
+        overlay.max_steps = 80;
+        Some(overlay)
+    }
+
+    fn color_attribute(&self) -> ColorAttribute {
+        match self.attribute_index {
+            0 => ColorAttribute::Speed,
+            1 => ColorAttribute::Age,
+            2 => ColorAttribute::Height,
+            3 => ColorAttribute::KineticEnergy,
+            4 => ColorAttribute::Density { cell_size: 1.0 },
+            _ => ColorAttribute::Custom {
+                name: "Vertical velocity",
+                value: |p| p.velocity.y,
+            },
+        }
+    }
+
+    /// Sample path for the selected path type: a lap around the origin that
+    /// rises and falls while the emitter turns a full circle.
+    fn demo_path(&self) -> Option<MotionPath> {
+        let turn = |i: f32| Quat::from_rotation_y(i * std::f32::consts::FRAC_PI_2);
+        let keyframes = vec![
+            Keyframe::new(0.0, vec3(8.0, 0.0, 0.0)),
+            Keyframe::new(1.0, vec3(0.0, 3.0, 8.0)).rotation(turn(1.0)),
+            Keyframe::new(2.0, vec3(-8.0, 0.0, 0.0)).rotation(turn(2.0)),
+            Keyframe::new(3.0, vec3(0.0, -3.0, -8.0)).rotation(turn(3.0)),
+            Keyframe::new(4.0, vec3(8.0, 0.0, 0.0)).rotation(turn(4.0)),
+        ];
+        let path = match self.path_index {
+            1 => MotionPath::linear(keyframes),
+            2 => MotionPath::catmull_rom(keyframes),
+            3 => MotionPath::bezier(vec![
+                Keyframe::new(0.0, vec3(8.0, 0.0, 0.0)),
+                Keyframe::new(0.0, vec3(8.0, 6.0, 10.0)),
+                Keyframe::new(0.0, vec3(-8.0, 6.0, 10.0)),
+                Keyframe::new(2.0, vec3(-8.0, 0.0, 0.0)).rotation(turn(2.0)),
+                Keyframe::new(0.0, vec3(-8.0, -6.0, -10.0)),
+                Keyframe::new(0.0, vec3(8.0, -6.0, -10.0)),
+                Keyframe::new(4.0, vec3(8.0, 0.0, 0.0)).rotation(turn(4.0)),
+            ]),
+            _ => return None,
+        };
+        Some(path.mode(self.path_mode).speed(self.path_speed))
+    }
+}
+
+// Helper: white dot fading to transparent at the edge, for billboards
+fn soft_dot_texture() -> Texture2D {
+    let size = 64u16;
+    let mut bytes = Vec::with_capacity(size as usize * size as usize * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let d = vec2(x as f32 + 0.5, y as f32 + 0.5) / size as f32 * 2.0 - Vec2::ONE;
+            let alpha = (1.0 - d.length()).clamp(0.0, 1.0);
+            bytes.extend([255, 255, 255, (alpha * alpha * 255.0) as u8]);
+        }
+    }
+    Texture2D::from_rgba8(size, size, &bytes)
+}
+
+/// A 4 × 4 sprite sheet of a ring that expands and fades, as a stand-in for
+/// an explosion flipbook.
+fn ring_sheet_texture() -> Texture2D {
+    let (frame, cells) = (32usize, 4usize);
+    let size = frame * cells;
+    let mut bytes = vec![0u8; size * size * 4];
+    for y in 0..size {
+        for x in 0..size {
+            let index = (y / frame) * cells + x / frame;
+            let t = index as f32 / (cells * cells - 1) as f32;
+            let d = vec2((x % frame) as f32 + 0.5, (y % frame) as f32 + 0.5) / frame as f32 * 2.0
+                - Vec2::ONE;
+            let ring = 1.0 - ((d.length() - (0.2 + 0.7 * t)).abs() * 6.0).min(1.0);
+            let alpha = ring * (1.0 - t * 0.7);
+            let offset = (y * size + x) * 4;
+            bytes[offset..offset + 4].copy_from_slice(&[255, 255, 255, (alpha * 255.0) as u8]);
+        }
+    }
+    Texture2D::from_rgba8(size as u16, size as u16, &bytes)
 }
 
 // Helper: convert HSV (h:0..1, s:0..1, v:0..1) to RGB Color
@@ -136,12 +531,28 @@ impl Scene for UnifiedEmitterScene {
 
     fn stop(&mut self) {
         self.particle_system = None;
+        self.simulation = None;
     }
 
     fn update(&mut self) -> Option<SceneName> {
         self.camera.update();
 
         let delta = get_frame_time();
+        if self.orbit_emitter && self.path_index == 0 {
+            self.orbit_angle += self.orbit_speed * delta;
+            let radius = 8.0;
+            let position = vec3(
+                self.orbit_angle.cos() * radius,
+                0.0,
+                self.orbit_angle.sin() * radius,
+            );
+            self.with_system(move |system| {
+                for emitter in system.emitters_mut() {
+                    emitter.set_position(position);
+                }
+            });
+        }
+        // a system on its own thread steps itself
         if let Some(system) = &mut self.particle_system {
             system.update(delta);
         }
@@ -194,17 +605,125 @@ impl Scene for UnifiedEmitterScene {
             ui.separator();
 
             // Spawn mode
-            let spawn_label = if self.spawn_volume {
-                "Volume"
-            } else {
-                "Surface"
+            let spawn_label = match self.spawn_index {
+                0 => "Volume",
+                1 => "Surface",
+                _ => "Even",
             };
             ui.label(None, &format!("Spawn: {spawn_label}"));
-            if ui.button(None, "Toggle Spawn Mode") {
-                self.spawn_volume = !self.spawn_volume;
+            if ui.button(None, "Next Spawn Mode") {
+                self.spawn_index = (self.spawn_index + 1) % 3;
+                self.rebuild_system();
+            }
+
+            let look_label = match self.look_index {
+                0 => "Crosses",
+                1 => "Billboards",
+                2 => "Flipbook",
+                3 => "Streak lines",
+                4 => "Streak quads",
+                5 => "Cubes",
+                6 => "Spheres",
+                7 => "OBJ shards",
+                _ => "Colormap",
+            };
+            ui.label(None, &format!("Particles: {look_label}"));
+            if ui.button(None, "Next Particle Look") {
+                self.look_index = (self.look_index + 1) % 9;
+                self.rebuild_system();
+            }
+            if self.look_index == 2 {
+                ui.label(None, &format!("Playback: {:?}", self.playback));
+                if ui.button(None, "Next Playback") {
+                    self.playback = match self.playback {
+                        Playback::OverLifetime => Playback::Loop,
+                        Playback::Loop => Playback::RandomStart,
+                        Playback::RandomStart => Playback::OverLifetime,
+                    };
+                    self.rebuild_system();
+                }
+            }
+            if self.look_index == 8 {
+                ui.label(
+                    None,
+                    &format!("Colour by: {}", self.color_attribute().name()),
+                );
+                // the style is only pushed to the system when one of these changes
+                let mut restyle = false;
+                if ui.button(None, "Next Attribute") {
+                    self.attribute_index = (self.attribute_index + 1) % 6;
+                    restyle = true;
+                }
+                ui.label(None, &format!("Colormap: {:?}", self.colormap));
+                if ui.button(None, "Next Colormap") {
+                    self.colormap = match self.colormap {
+                        Colormap::Viridis => Colormap::Magma,
+                        Colormap::Magma => Colormap::Plasma,
+                        Colormap::Plasma => Colormap::Turbo,
+                        Colormap::Turbo => Colormap::Diverging,
+                        Colormap::Diverging => Colormap::Viridis,
+                    };
+                    restyle = true;
+                }
+                let range_label = if self.auto_range { "Auto" } else { "Manual" };
+                ui.label(None, &format!("Range: {range_label}"));
+                if ui.button(None, "Toggle Range") {
+                    self.auto_range = !self.auto_range;
+                    restyle = true;
+                }
+                if !self.auto_range {
+                    let range_max = self.range_max;
+                    ui.slider(hash!(), "Range max", 0.1f32..50.0, &mut self.range_max);
+                    restyle |= self.range_max != range_max;
+                }
+                if restyle {
+                    let style = self.colormap_style();
+                    self.with_system(move |system| system.set_style(style.clone()));
+                }
+            }
+
+            ui.label(None, &format!("Blend: {:?}", self.blend_mode));
+            if ui.button(None, "Next Blend Mode") {
+                self.blend_mode = match self.blend_mode {
+                    BlendMode::Alpha => BlendMode::Additive,
+                    BlendMode::Additive => BlendMode::Premultiplied,
+                    BlendMode::Premultiplied => BlendMode::Multiply,
+                    BlendMode::Multiply => BlendMode::Alpha,
+                };
+                self.rebuild_system();
+            }
+            let trail_label = match self.trail_index {
+                0 => "Off",
+                1 => "Lines",
+                _ => "Ribbons",
+            };
+            ui.label(None, &format!("Trails: {trail_label}"));
+            if ui.button(None, "Next Trail Style") {
+                self.trail_index = (self.trail_index + 1) % 3;
                 self.rebuild_system();
             }
 
+            let depth_write_label = if self.depth_write { "On" } else { "Off" };
+            ui.label(None, &format!("Depth write: {depth_write_label}"));
+            if ui.button(None, "Toggle Depth Write") {
+                self.depth_write = !self.depth_write;
+                self.rebuild_system();
+            }
+            let depth_test_label = if self.depth_test { "On" } else { "Off" };
+            ui.label(None, &format!("Depth test: {depth_test_label}"));
+            if ui.button(None, "Toggle Depth Test") {
+                self.depth_test = !self.depth_test;
+                self.rebuild_system();
+            }
+            // compare sorted and storage-order blending on the live system
+            let depth_sort_label = if self.depth_sort { "On" } else { "Off" };
+            ui.label(None, &format!("Depth sort: {depth_sort_label}"));
+            if ui.button(None, "Toggle Depth Sort") {
+                self.depth_sort = !self.depth_sort;
+                let depth_sort = self.depth_sort;
+                self.with_system(move |system| system.set_depth_sort(depth_sort));
+            }
+
             ui.separator();
 
             // Size slider
@@ -339,6 +858,190 @@ impl Scene for UnifiedEmitterScene {
                 self.rebuild_system();
             }
 
+            ui.label(None, "Max particles (0 = unlimited)");
+            let max_particles = self.max_particles;
+            ui.slider(
+                hash!(),
+                "MaxParticles",
+                0.0f32..5000.0f32,
+                &mut self.max_particles,
+            );
+            if self.max_particles != max_particles {
+                let cap = self.particle_cap();
+                self.with_system(move |system| system.set_max_particles(cap));
+            }
+
+            ui.separator();
+            ui.label(None, "Forces");
+            let forces = self.forces();
+            ui.slider(hash!(), "Gravity", 0.0f32..20.0f32, &mut self.gravity);
+            ui.slider(hash!(), "Drag", 0.0f32..5.0f32, &mut self.drag);
+            ui.slider(
+                hash!(),
+                "Attractor",
+                -200.0f32..200.0f32,
+                &mut self.attractor,
+            );
+            if self.forces() != forces {
+                self.field_forces = self.forces();
+                let forces = self.forces();
+                self.with_system(move |system| system.set_forces(forces.clone()));
+            }
+            let floor_label = if self.floor_bounce { "On" } else { "Off" };
+            ui.label(None, &format!("Floor bounce: {floor_label}"));
+            if ui.button(None, "Toggle Floor Bounce") {
+                self.floor_bounce = !self.floor_bounce;
+                self.rebuild_system();
+            }
+            let shadows_label = if self.shadows { "On" } else { "Off" };
+            ui.label(None, &format!("Floor shadows: {shadows_label}"));
+            if ui.button(None, "Toggle Shadows") {
+                self.shadows = !self.shadows;
+                self.rebuild_system();
+            }
+
+            let kernel_label = match self.kernel {
+                Kernel::Scalar => "Scalar",
+                Kernel::Simd => "SIMD",
+            };
+            ui.label(None, &format!("Update kernels: {kernel_label}"));
+            if ui.button(None, "Toggle Kernels") {
+                self.kernel = match self.kernel {
+                    Kernel::Scalar => Kernel::Simd,
+                    Kernel::Simd => Kernel::Scalar,
+                };
+                let kernel = self.kernel;
+                self.with_system(move |system| system.set_kernel(kernel));
+            }
+
+            let thread_label = if self.simulation.is_some() {
+                "On"
+            } else {
+                "Off"
+            };
+            ui.label(None, &format!("Simulation thread: {thread_label}"));
+            if ui.button(None, "Toggle Simulation Thread") {
+                self.toggle_simulation_thread();
+            }
+
+            ui.separator();
+            let lit_label = if self.lit { "On" } else { "Off" };
+            ui.label(None, &format!("Lighting (meshes, billboards): {lit_label}"));
+            if ui.button(None, "Toggle Lighting") {
+                self.lit = !self.lit;
+            }
+            ui.slider(hash!(), "Ambient", 0.0f32..1.0f32, &mut self.ambient);
+            ui.slider(hash!(), "Sun", 0.0f32..1.5f32, &mut self.sun);
+            ui.slider(
+                hash!(),
+                "Sun angle",
+                0.0f32..std::f32::consts::TAU,
+                &mut self.sun_angle,
+            );
+            let light_label = if self.floor_light { "On" } else { "Off" };
+            ui.label(None, &format!("Floor point light: {light_label}"));
+            if ui.button(None, "Toggle Point Light") {
+                self.floor_light = !self.floor_light;
+            }
+            self.update_lighting();
+
+            let fog_label = match self.fog_index {
+                0 => "Off",
+                1 => "Linear",
+                _ => "Exponential",
+            };
+            ui.label(None, &format!("Fog: {fog_label}"));
+            if ui.button(None, "Next Fog") {
+                self.fog_index = (self.fog_index + 1) % 3;
+            }
+
+            let field_label = match self.field_index {
+                0 => "Off",
+                1 => "Grid",
+                _ => "Slice",
+            };
+            ui.label(None, &format!("Force field: {field_label}"));
+            if ui.button(None, "Next Force Field") {
+                self.field_index = (self.field_index + 1) % 3;
+                self.field = self.field_overlay();
+            }
+            let streamline_label = if self.streamlines { "On" } else { "Off" };
+            ui.label(None, &format!("Streamlines: {streamline_label}"));
+            if ui.button(None, "Toggle Streamlines") {
+                self.streamlines = !self.streamlines;
+                self.field = self.field_overlay();
+            }
+
+            ui.separator();
+            let orbit_label = if self.orbit_emitter { "On" } else { "Off" };
+            ui.label(None, &format!("Orbit emitter: {orbit_label}"));
+            if ui.button(None, "Toggle Orbit") {
+                self.orbit_emitter = !self.orbit_emitter;
+                self.orbit_angle = 0.0;
+                self.rebuild_system();
+            }
+            ui.slider(
+                hash!(),
+                "Orbit Speed",
+                0.0f32..20.0f32,
+                &mut self.orbit_speed,
+            );
+
+            ui.separator();
+            let path_label = match self.path_index {
+                1 => "Keyframes",
+                2 => "Catmull-Rom",
+                3 => "Bezier",
+                _ => "None",
+            };
+            ui.label(None, &format!("Motion path: {path_label}"));
+            if ui.button(None, "Next Path") {
+                self.path_index = (self.path_index + 1) % 4;
+                self.rebuild_system();
+            }
+            let mode_label = match self.path_mode {
+                PathMode::Once => "Once",
+                PathMode::Loop => "Loop",
+                PathMode::PingPong => "Ping-pong",
+            };
+            ui.label(None, &format!("Path mode: {mode_label}"));
+            if ui.button(None, "Next Path Mode") {
+                self.path_mode = match self.path_mode {
+                    PathMode::Once => PathMode::Loop,
+                    PathMode::Loop => PathMode::PingPong,
+                    PathMode::PingPong => PathMode::Once,
+                };
+                self.rebuild_system();
+            }
+            let path_speed = self.path_speed;
+            ui.slider(hash!(), "Path Speed", 0.0f32..4.0f32, &mut self.path_speed);
+            if self.path_speed != path_speed {
+                let path_speed = self.path_speed;
+                self.with_system(move |system| {
+                    for emitter in system.emitters_mut() {
+                        if let Some(path) = emitter.path_mut() {
+                            path.set_speed(path_speed);
+                        }
+                    }
+                });
+            }
+
+            ui.separator();
+
+            ui.label(None, &format!("Seed: {}", self.seed));
+            ui.input_text(hash!(), "Seed", &mut self.seed_text);
+            if let Ok(seed) = self.seed_text.trim().parse::<u64>()
+                && seed != self.seed
+            {
+                self.seed = seed;
+                self.rebuild_system();
+            }
+            if ui.button(None, "Random Seed") {
+                self.seed = rand::rand() as u64;
+                self.seed_text = self.seed.to_string();
+                self.rebuild_system();
+            }
+
             ui.separator();
 
             if ui.button(None, "Rebuild System") {
@@ -417,15 +1120,31 @@ impl Scene for UnifiedEmitterScene {
     fn draw(&self) {
         // Use a dark gray background so the UI (which uses light/white panels)
         // doesn't feel like a white rectangle on pure black.
-        clear_background(Color::new(0.06, 0.06, 0.06, 1.0));
+        clear_background(BACKGROUND);
 
         // set interactive camera
         set_camera(&self.camera.camera());
 
         // draw room and particles
-        self.draw_room();
+        let mut ctx = self.camera.render_context();
+        ctx.fog = self.fog();
+        self.draw_room(&ctx);
+        if let Some(field) = &self.field {
+            match &self.particle_system {
+                Some(system) => field.draw(system.active_forces(), &ctx),
+                None => field.draw(&self.field_forces, &ctx),
+            }
+        }
+        let ctx = if self.lit {
+            ctx.with_lighting(&self.lighting)
+        } else {
+            ctx
+        };
         if let Some(system) = &self.particle_system {
-            system.draw();
+            system.draw(&ctx);
+        }
+        if let Some(simulation) = &self.simulation {
+            simulation.draw(&ctx);
         }
 
         // Draw color picker overlay if requested (visual only)
@@ -507,5 +1226,22 @@ impl Scene for UnifiedEmitterScene {
         // UI is drawn in `update()` via macroquad windows, nothing else to draw here.
 
         set_default_camera();
+
+        // left of the screen, clear of the panel and below the cap warning
+        let legend = (20.0, 110.0);
+        match (&self.particle_system, &self.simulation) {
+            (Some(system), _) => system.draw_legend(legend.0, legend.1),
+            (None, Some(simulation)) => simulation.draw_legend(legend.0, legend.1),
+            (None, None) => {}
+        }
+
+        let dropped = match (&self.particle_system, &self.simulation) {
+            (Some(system), _) => system.dropped_count(),
+            (None, Some(simulation)) => simulation.dropped_count(),
+            (None, None) => 0,
+        };
+        if dropped > 0 {
+            draw_text("Particle cap reached", 10.0, 60.0, 24.0, RED);
+        }
     }
 }